@@ -0,0 +1,169 @@
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::process::ExitCode;
+
+use garnish_lang_annotations_collector::{parse_sink_spec, sinks_from_config, Collector, Sink};
+use serde_json::Value;
+
+mod server;
+
+const USAGE: &str = "Usage: garnish-annotations-lsp [OPTIONS]
+
+Language server for garnish annotations over stdin and stdout. Provides document symbols,
+folding ranges and diagnostics for the configured sinks.
+
+Options:
+    -s, --sink <SPEC>      Add a sink, e.g. '@Test=count:1,token:EndExpression'
+    -c, --config <FILE>    Read sink definitions from a .toml or .json file, or any
+                           other file with one definition per line
+    -h, --help             Print this message";
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    if args.iter().any(|arg| arg == "-h" || arg == "--help") {
+        println!("{}", USAGE);
+        return ExitCode::SUCCESS;
+    }
+
+    let result = parse_sinks(&args).and_then(|sinks| {
+        let mut server = server::Server::new(Collector::new(sinks));
+        let stdin = io::stdin();
+        let mut input = stdin.lock();
+        let mut output = io::stdout();
+
+        while let Some(message) = read_message(&mut input)? {
+            let (replies, exit) = server.handle(&message);
+            for reply in replies.iter() {
+                write_message(&mut output, reply)?;
+            }
+            if exit {
+                break;
+            }
+        }
+
+        Ok(())
+    });
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn parse_sinks(args: &[String]) -> Result<Vec<Sink>, String> {
+    let mut sinks = vec![];
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-s" | "--sink" => sinks.push(parse_sink_spec(value(arg, args.next())?)?),
+            "-c" | "--config" => {
+                let path = value(arg, args.next())?;
+                let config = fs::read_to_string(path)
+                    .map_err(|err| format!("Could not read config '{}': {}", path, err))?;
+                sinks.extend(
+                    sinks_from_config(path, &config).map_err(|err| format!("{}: {}", path, err))?,
+                );
+            }
+            arg => return Err(format!("Unknown argument '{}'\n\n{}", arg, USAGE)),
+        }
+    }
+
+    if sinks.is_empty() {
+        return Err(format!("No sinks defined\n\n{}", USAGE));
+    }
+
+    Ok(sinks)
+}
+
+fn value<'a>(flag: &str, value: Option<&'a String>) -> Result<&'a str, String> {
+    value
+        .map(String::as_str)
+        .ok_or_else(|| format!("Missing value for '{}'", flag))
+}
+
+/// Reads one `Content-Length` framed message, `None` once input has ended.
+fn read_message<R: BufRead>(input: &mut R) -> Result<Option<Value>, String> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        let read = input
+            .read_line(&mut line)
+            .map_err(|err| format!("Could not read message header: {}", err))?;
+        if read == 0 {
+            return Ok(None);
+        }
+
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                length = Some(
+                    value
+                        .trim()
+                        .parse::<usize>()
+                        .map_err(|_| format!("Invalid Content-Length '{}'", value.trim()))?,
+                );
+            }
+        }
+    }
+
+    let length = length.ok_or_else(|| "Message without Content-Length".to_string())?;
+    let mut body = vec![0; length];
+    input
+        .read_exact(&mut body)
+        .map_err(|err| format!("Could not read message body: {}", err))?;
+
+    serde_json::from_slice(&body)
+        .map(Some)
+        .map_err(|err| format!("Invalid message: {}", err))
+}
+
+fn write_message<W: Write>(output: &mut W, message: &Value) -> Result<(), String> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)
+        .and_then(|_| output.flush())
+        .map_err(|err| format!("Could not write message: {}", err))
+}
+
+#[cfg(test)]
+mod framing {
+    use serde_json::json;
+
+    use crate::{read_message, write_message};
+
+    #[test]
+    fn round_trip() {
+        let mut output = vec![];
+        write_message(&mut output, &json!({"id": 1, "result": null})).unwrap();
+        write_message(&mut output, &json!({"method": "exit"})).unwrap();
+
+        let mut input = output.as_slice();
+
+        assert_eq!(
+            read_message(&mut input).unwrap(),
+            Some(json!({"id": 1, "result": null}))
+        );
+        assert_eq!(
+            read_message(&mut input).unwrap(),
+            Some(json!({"method": "exit"}))
+        );
+        assert_eq!(read_message(&mut input).unwrap(), None);
+    }
+
+    #[test]
+    fn missing_length() {
+        let mut input = "Content-Type: json\r\n\r\n{}".as_bytes();
+
+        assert_eq!(
+            read_message(&mut input),
+            Err("Message without Content-Length".to_string())
+        );
+    }
+}
@@ -0,0 +1,366 @@
+use std::collections::HashMap;
+
+use garnish_lang_annotations_collector::lsp::{
+    diagnostics, document_symbols, folding_ranges, Diagnostic, DocumentSymbol, Position, Range,
+    Severity,
+};
+use garnish_lang_annotations_collector::{Collector, TokenBlock};
+use serde_json::{json, Value};
+
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const SYMBOL_KIND_FUNCTION: i64 = 12;
+
+// Unit characters are counted in, agreed on with the client when initializing
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+enum PositionEncoding {
+    Utf16,
+    Utf32,
+}
+
+impl PositionEncoding {
+    fn name(&self) -> &'static str {
+        match self {
+            PositionEncoding::Utf16 => "utf-16",
+            PositionEncoding::Utf32 => "utf-32",
+        }
+    }
+}
+
+pub struct Server {
+    collector: Collector,
+    documents: HashMap<String, String>,
+    encoding: PositionEncoding,
+}
+
+impl Server {
+    pub fn new(collector: Collector) -> Self {
+        Self {
+            collector,
+            documents: HashMap::new(),
+            encoding: PositionEncoding::Utf16,
+        }
+    }
+
+    /// Handles one incoming message, returning the messages to send back and whether
+    /// the server should exit.
+    pub fn handle(&mut self, message: &Value) -> (Vec<Value>, bool) {
+        let method = message["method"].as_str().unwrap_or("");
+        let params = &message["params"];
+        let uri = params["textDocument"]["uri"]
+            .as_str()
+            .unwrap_or("")
+            .to_string();
+
+        let result = match method {
+            "initialize" => {
+                // Lexer columns count characters, the same as utf-32. Clients not offering it
+                // get the utf-16 the protocol defaults to.
+                let offered = params["capabilities"]["general"]["positionEncodings"]
+                    .as_array()
+                    .is_some_and(|encodings| encodings.contains(&json!("utf-32")));
+                self.encoding = match offered {
+                    true => PositionEncoding::Utf32,
+                    false => PositionEncoding::Utf16,
+                };
+                Ok(json!({
+                    "capabilities": {
+                        "positionEncoding": self.encoding.name(),
+                        "textDocumentSync": 1,
+                        "documentSymbolProvider": true,
+                        "foldingRangeProvider": true,
+                    },
+                    "serverInfo": { "name": "garnish-annotations-lsp" },
+                }))
+            }
+            "shutdown" => Ok(Value::Null),
+            "exit" => return (vec![], true),
+            "textDocument/didOpen" => {
+                let text = params["textDocument"]["text"].as_str().unwrap_or("");
+                self.documents.insert(uri.clone(), text.to_string());
+                return (vec![self.publish_diagnostics(&uri)], false);
+            }
+            "textDocument/didChange" => {
+                // Full sync, the last change holds the whole document
+                if let Some(text) = params["contentChanges"]
+                    .as_array()
+                    .and_then(|changes| changes.last())
+                    .and_then(|change| change["text"].as_str())
+                {
+                    self.documents.insert(uri.clone(), text.to_string());
+                }
+                return (vec![self.publish_diagnostics(&uri)], false);
+            }
+            "textDocument/didClose" => {
+                self.documents.remove(&uri);
+                return (
+                    vec![notification(
+                        "textDocument/publishDiagnostics",
+                        json!({ "uri": uri, "diagnostics": [] }),
+                    )],
+                    false,
+                );
+            }
+            "textDocument/documentSymbol" => self.collect(&uri).map(|blocks| {
+                let encoder = self.encoder(&uri);
+                Value::Array(
+                    document_symbols(&blocks)
+                        .iter()
+                        .map(|symbol| encoder.symbol_json(symbol))
+                        .collect(),
+                )
+            }),
+            "textDocument/foldingRange" => self.collect(&uri).map(|blocks| {
+                Value::Array(
+                    folding_ranges(&blocks)
+                        .iter()
+                        .map(|range| {
+                            json!({ "startLine": range.start_line, "endLine": range.end_line })
+                        })
+                        .collect(),
+                )
+            }),
+            method => Err((METHOD_NOT_FOUND, format!("Unknown method '{}'", method))),
+        };
+
+        // Notifications have no id and get no response
+        match message.get("id") {
+            None => (vec![], false),
+            Some(id) => {
+                let response = match result {
+                    Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+                    Err((code, message)) => json!({
+                        "jsonrpc": "2.0",
+                        "id": id,
+                        "error": { "code": code, "message": message },
+                    }),
+                };
+                (vec![response], false)
+            }
+        }
+    }
+
+    fn collect(&self, uri: &str) -> Result<Vec<TokenBlock>, (i64, String)> {
+        let text = self
+            .documents
+            .get(uri)
+            .ok_or_else(|| (INVALID_PARAMS, format!("Document '{}' is not open", uri)))?;
+        self.collector
+            .with_declarations(text)
+            .map_err(|err| (INVALID_PARAMS, err.to_string()))?
+            .collect_tokens_from_input(text)
+            .map_err(|err| (INVALID_PARAMS, err))
+    }
+
+    fn encoder(&self, uri: &str) -> Encoder<'_> {
+        let text = self.documents.get(uri).map(String::as_str).unwrap_or("");
+        Encoder {
+            lines: text.split('\n').collect(),
+            encoding: self.encoding,
+        }
+    }
+
+    fn publish_diagnostics(&self, uri: &str) -> Value {
+        let text = self.documents.get(uri).map(String::as_str).unwrap_or("");
+        let encoder = self.encoder(uri);
+        let diagnostics: Vec<Value> = diagnostics(&self.collector, text)
+            .iter()
+            .map(|diagnostic| encoder.diagnostic_json(diagnostic))
+            .collect();
+
+        notification(
+            "textDocument/publishDiagnostics",
+            json!({ "uri": uri, "diagnostics": diagnostics }),
+        )
+    }
+}
+
+fn notification(method: &str, params: Value) -> Value {
+    json!({ "jsonrpc": "2.0", "method": method, "params": params })
+}
+
+// Writes positions of a document in the negotiated encoding
+struct Encoder<'a> {
+    lines: Vec<&'a str>,
+    encoding: PositionEncoding,
+}
+
+impl Encoder<'_> {
+    fn position_json(&self, position: &Position) -> Value {
+        let character = match self.encoding {
+            PositionEncoding::Utf32 => position.character,
+            PositionEncoding::Utf16 => match self.lines.get(position.line) {
+                None => position.character,
+                Some(line) => {
+                    let prefix: usize = line
+                        .chars()
+                        .take(position.character)
+                        .map(char::len_utf16)
+                        .sum();
+                    // Positions past the end of the line keep counting single units
+                    prefix + position.character.saturating_sub(line.chars().count())
+                }
+            },
+        };
+        json!({ "line": position.line, "character": character })
+    }
+
+    fn range_json(&self, range: &Range) -> Value {
+        json!({
+            "start": self.position_json(&range.start),
+            "end": self.position_json(&range.end),
+        })
+    }
+
+    fn symbol_json(&self, symbol: &DocumentSymbol) -> Value {
+        let mut value = json!({
+            "name": symbol.name,
+            "kind": SYMBOL_KIND_FUNCTION,
+            "range": self.range_json(&symbol.range),
+            "selectionRange": self.range_json(&symbol.selection_range),
+            "children": symbol
+                .children
+                .iter()
+                .map(|child| self.symbol_json(child))
+                .collect::<Vec<Value>>(),
+        });
+        if let Some(detail) = &symbol.detail {
+            value["detail"] = json!(detail);
+        }
+        value
+    }
+
+    fn diagnostic_json(&self, diagnostic: &Diagnostic) -> Value {
+        json!({
+            "range": self.range_json(&diagnostic.range),
+            "severity": match diagnostic.severity {
+                Severity::Error => 1,
+                Severity::Warning => 2,
+            },
+            "source": "garnish-annotations",
+            "message": diagnostic.message,
+        })
+    }
+}
+
+#[cfg(test)]
+mod handling {
+    use garnish_lang_annotations_collector::{parse_sink_spec, Collector};
+    use serde_json::{json, Value};
+
+    use crate::server::Server;
+
+    fn server() -> Server {
+        let mut server = Server::new(Collector::new(vec![parse_sink_spec(
+            "@Test=count:1,token:EndExpression",
+        )
+        .unwrap()]));
+        server.handle(&json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/didOpen",
+            "params": { "textDocument": { "uri": "file:///test.garnish", "text": "@Test first {\n 5\n}\n" } },
+        }));
+        server
+    }
+
+    #[test]
+    fn initialize() {
+        let (replies, exit) = server()
+            .handle(&json!({"jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {}}));
+
+        assert!(!exit);
+        assert_eq!(replies[0]["id"], json!(1));
+        assert_eq!(
+            replies[0]["result"]["capabilities"]["documentSymbolProvider"],
+            json!(true)
+        );
+        assert_eq!(
+            replies[0]["result"]["capabilities"]["positionEncoding"],
+            json!("utf-16")
+        );
+    }
+
+    #[test]
+    fn position_encodings() {
+        let text = "x = \"😀\" @Test first {\n 5\n}\n";
+        let open = json!({
+            "method": "textDocument/didOpen",
+            "params": { "textDocument": { "uri": "file:///test.garnish", "text": text } },
+        });
+        let symbols = json!({
+            "id": 2,
+            "method": "textDocument/documentSymbol",
+            "params": { "textDocument": { "uri": "file:///test.garnish" } },
+        });
+        let start = |encodings: Value| {
+            let mut server = server();
+            let (replies, _) = server.handle(&json!({
+                "id": 1,
+                "method": "initialize",
+                "params": { "capabilities": { "general": { "positionEncodings": encodings } } },
+            }));
+            server.handle(&open);
+            let (symbols, _) = server.handle(&symbols);
+            (
+                replies[0]["result"]["capabilities"]["positionEncoding"].clone(),
+                symbols[0]["result"][0]["selectionRange"]["start"]["character"].clone(),
+            )
+        };
+
+        assert_eq!(
+            start(json!(["utf-16", "utf-32"])),
+            (json!("utf-32"), json!(8))
+        );
+        assert_eq!(start(json!(["utf-16"])), (json!("utf-16"), json!(9)));
+    }
+
+    #[test]
+    fn document_symbols_and_folding() {
+        let mut server = server();
+        let params = json!({ "textDocument": { "uri": "file:///test.garnish" } });
+
+        let (symbols, _) = server
+            .handle(&json!({"id": 2, "method": "textDocument/documentSymbol", "params": params}));
+        let (folding, _) = server
+            .handle(&json!({"id": 3, "method": "textDocument/foldingRange", "params": params}));
+
+        assert_eq!(symbols[0]["result"][0]["name"], json!("@Test"));
+        assert_eq!(symbols[0]["result"][0]["detail"], json!("first"));
+        assert_eq!(
+            folding[0]["result"],
+            json!([{ "startLine": 0, "endLine": 2 }])
+        );
+    }
+
+    #[test]
+    fn diagnostics_published_on_change() {
+        let mut server = server();
+
+        let (replies, _) = server.handle(&json!({
+            "method": "textDocument/didChange",
+            "params": {
+                "textDocument": { "uri": "file:///test.garnish" },
+                "contentChanges": [{ "text": "@Test first {\n 5\n" }],
+            },
+        }));
+
+        assert_eq!(
+            replies[0]["method"],
+            json!("textDocument/publishDiagnostics")
+        );
+        assert_eq!(replies[0]["params"]["diagnostics"][0]["severity"], json!(1));
+    }
+
+    #[test]
+    fn unknown_request_and_exit() {
+        let mut server = server();
+
+        let (replies, _) =
+            server.handle(&json!({"id": 4, "method": "textDocument/hover", "params": {}}));
+        assert_eq!(replies[0]["error"]["code"], json!(-32601));
+
+        let (replies, exit) = server.handle(&json!({"method": "exit"}));
+        assert!(replies.is_empty());
+        assert!(exit);
+    }
+}
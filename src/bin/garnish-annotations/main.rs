@@ -0,0 +1,267 @@
+use std::fs;
+use std::io::{self, IsTerminal, Read};
+use std::process::ExitCode;
+
+use garnish_lang_annotations_collector::{
+    parse_sink_spec, render_diagnostic, sinks_from_config, Collector, DiagnosticStyle, Sink,
+};
+
+mod output;
+
+const USAGE: &str = "Usage: garnish-annotations [dump] [OPTIONS] [FILE]
+       garnish-annotations check [OPTIONS] FILE...
+
+dump     Collects annotations from a garnish file, or stdin when FILE is omitted or '-',
+         and prints the resulting block tree.
+check    Collects annotations from each file and reports every annotation without a sink,
+         unterminated part and invalid nesting. Exits with a failure status if any file has
+         problems.
+
+Sinks declared in a file with @annotation are added for that file.
+
+Options:
+    -s, --sink <SPEC>      Add a sink, e.g. '@Test=count:1,token:EndExpression'
+    -c, --config <FILE>    Read sink definitions from a .toml or .json file, or any
+                           other file with one definition per line
+    -f, --format <FORMAT>  Output format, 'text' (default) or 'json'
+    -p, --pretty           Show check errors with source snippets, colored on a terminal
+    -h, --help             Print this message
+
+Part definitions: newline, expression, count:N, token:TokenType, annotation:Name,
+start-end:TokenType:TokenType
+Flags: target, modifier, observe
+Nesting rules: top-level, parent:Name, child:Name";
+
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+enum Format {
+    Text,
+    Json,
+}
+
+#[derive(Debug, Eq, PartialEq)]
+struct Options {
+    sinks: Vec<Sink>,
+    format: Format,
+    pretty: bool,
+    inputs: Vec<String>,
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    if args.iter().any(|arg| arg == "-h" || arg == "--help") {
+        println!("{}", USAGE);
+        return ExitCode::SUCCESS;
+    }
+
+    let result = match args.first().map(String::as_str) {
+        Some("check") => check(&args[1..]),
+        Some("dump") => dump(&args[1..]),
+        _ => dump(&args),
+    };
+
+    match result {
+        Ok(code) => code,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn dump(args: &[String]) -> Result<ExitCode, String> {
+    let options = parse_options(args)?;
+    if options.inputs.len() > 1 {
+        return Err(format!("Unexpected argument '{}'", options.inputs[1]));
+    }
+
+    let input = read_input(options.inputs.first().map(String::as_str))?;
+    let blocks = Collector::new(options.sinks)
+        .with_declarations(&input)
+        .map_err(|err| err.to_string())?
+        .collect_tokens_from_input(&input)?;
+
+    match options.format {
+        Format::Text => print!("{}", output::text(&blocks)),
+        Format::Json => println!("{}", output::json(&blocks)?),
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
+
+fn check(args: &[String]) -> Result<ExitCode, String> {
+    let options = parse_options(args)?;
+    if options.inputs.is_empty() {
+        return Err(format!("No files to check\n\n{}", USAGE));
+    }
+
+    let collector = Collector::new(options.sinks);
+    let mut failures = 0;
+    for path in options.inputs.iter() {
+        let input = read_input(Some(path))?;
+        let diagnostics = match collector.with_declarations(&input) {
+            Ok(collector) => collector.recover_input(&input).1,
+            Err(diagnostic) => vec![diagnostic],
+        };
+        for diagnostic in diagnostics.iter() {
+            match options.pretty {
+                true => {
+                    let style = match io::stderr().is_terminal() {
+                        true => DiagnosticStyle::Ansi,
+                        false => DiagnosticStyle::Plain,
+                    };
+                    eprintln!("{}", render_diagnostic(diagnostic, &input, path, style));
+                }
+                false => eprintln!(
+                    "{}:{}:{}: {}: {}",
+                    path,
+                    diagnostic.line() + 1,
+                    diagnostic.column() + 1,
+                    diagnostic.severity(),
+                    diagnostic.message()
+                ),
+            }
+        }
+        if !diagnostics.is_empty() {
+            failures += 1;
+        }
+    }
+
+    match failures {
+        0 => Ok(ExitCode::SUCCESS),
+        _ => {
+            eprintln!(
+                "{} of {} files failed annotation checks",
+                failures,
+                options.inputs.len()
+            );
+            Ok(ExitCode::FAILURE)
+        }
+    }
+}
+
+fn parse_options(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        sinks: vec![],
+        format: Format::Text,
+        pretty: false,
+        inputs: vec![],
+    };
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-s" | "--sink" => options
+                .sinks
+                .push(parse_sink_spec(value(arg, args.next())?)?),
+            "-c" | "--config" => {
+                let path = value(arg, args.next())?;
+                let config = fs::read_to_string(path)
+                    .map_err(|err| format!("Could not read config '{}': {}", path, err))?;
+                options.sinks.extend(
+                    sinks_from_config(path, &config).map_err(|err| format!("{}: {}", path, err))?,
+                );
+            }
+            "-p" | "--pretty" => options.pretty = true,
+            "-f" | "--format" => {
+                options.format = match value(arg, args.next())? {
+                    "text" => Format::Text,
+                    "json" => Format::Json,
+                    format => return Err(format!("Unknown format '{}'", format)),
+                }
+            }
+            flag if flag.starts_with('-') && flag != "-" => {
+                return Err(format!("Unknown option '{}'\n\n{}", flag, USAGE))
+            }
+            path => options.inputs.push(path.to_string()),
+        }
+    }
+
+    if options.sinks.is_empty() {
+        return Err(format!("No sinks defined\n\n{}", USAGE));
+    }
+
+    Ok(options)
+}
+
+fn value<'a>(flag: &str, value: Option<&'a String>) -> Result<&'a str, String> {
+    value
+        .map(String::as_str)
+        .ok_or_else(|| format!("Missing value for '{}'", flag))
+}
+
+fn read_input(path: Option<&str>) -> Result<String, String> {
+    match path {
+        None | Some("-") => {
+            let mut input = String::new();
+            io::stdin()
+                .read_to_string(&mut input)
+                .map_err(|err| format!("Could not read stdin: {}", err))?;
+            Ok(input)
+        }
+        Some(path) => {
+            fs::read_to_string(path).map_err(|err| format!("Could not read '{}': {}", path, err))
+        }
+    }
+}
+
+#[cfg(test)]
+mod options {
+    use garnish_lang_annotations_collector::{PartBehavior, PartParser, Sink};
+
+    use crate::{parse_options, Format, Options};
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn sinks_format_and_input() {
+        let options = parse_options(&args(&[
+            "--sink",
+            "@Test=newline",
+            "-s",
+            "@Skip",
+            "--format",
+            "json",
+            "test.garnish",
+        ]))
+        .unwrap();
+
+        assert_eq!(
+            options,
+            Options {
+                sinks: vec![
+                    Sink::new("@Test").part(PartParser::new(PartBehavior::UntilNewline)),
+                    Sink::new("@Skip"),
+                ],
+                format: Format::Json,
+                pretty: false,
+                inputs: vec!["test.garnish".to_string()],
+            }
+        );
+    }
+
+    #[test]
+    fn requires_sinks() {
+        assert!(parse_options(&args(&["test.garnish"]))
+            .unwrap_err()
+            .starts_with("No sinks defined"));
+    }
+
+    #[test]
+    fn missing_value() {
+        assert_eq!(
+            parse_options(&args(&["--sink"])),
+            Err("Missing value for '--sink'".to_string())
+        );
+    }
+
+    #[test]
+    fn unknown_format() {
+        assert_eq!(
+            parse_options(&args(&["-s", "@Test", "-f", "xml"])),
+            Err("Unknown format 'xml'".to_string())
+        );
+    }
+}
@@ -0,0 +1,108 @@
+use garnish_lang_annotations_collector::TokenBlock;
+use garnish_lang_compiler::lex::LexerToken;
+
+pub fn text(blocks: &[TokenBlock]) -> String {
+    let mut output = String::new();
+    for block in blocks {
+        write_block(&mut output, block, 0);
+    }
+    output
+}
+
+pub fn json(blocks: &[TokenBlock]) -> Result<String, String> {
+    serde_json::to_string_pretty(blocks).map_err(|err| err.to_string())
+}
+
+fn write_block(output: &mut String, block: &TokenBlock, depth: usize) {
+    let indent = "  ".repeat(depth);
+
+    if block.annotation_text().is_empty() {
+        output.push_str(&format!(
+            "{}<root> {:?}\n",
+            indent,
+            token_text(block.tokens())
+        ));
+        return;
+    }
+
+    output.push_str(&format!("{}{}\n", indent, block.annotation_text()));
+    write_contents(output, block, depth);
+}
+
+// Modifiers, parts, target and nested blocks of an annotated block
+fn write_contents(output: &mut String, block: &TokenBlock, depth: usize) {
+    let indent = "  ".repeat(depth);
+
+    for modifier in block.modifiers() {
+        output.push_str(&format!(
+            "{}  modifier {}\n",
+            indent,
+            modifier.annotation_text()
+        ));
+        write_contents(output, modifier, depth + 1);
+    }
+    for (index, part) in block.parts().iter().enumerate() {
+        output.push_str(&format!("{}  [{}] {:?}\n", indent, index, token_text(part)));
+    }
+    if !block.target().is_empty() {
+        output.push_str(&format!(
+            "{}  target {:?}\n",
+            indent,
+            token_text(block.target())
+        ));
+    }
+    for child in block.blocks() {
+        write_block(output, child, depth + 1);
+    }
+}
+
+fn token_text(tokens: &[LexerToken]) -> String {
+    tokens
+        .iter()
+        .map(|token| token.get_text().as_str())
+        .collect()
+}
+
+#[cfg(test)]
+mod text_output {
+    use garnish_lang_annotations_collector::{Collector, PartBehavior, PartParser, Sink};
+
+    use crate::output::text;
+
+    #[test]
+    fn nested_tree() {
+        let blocks = Collector::new(vec![
+            Sink::new("@Test")
+                .part(PartParser::new(PartBehavior::TokenCount(1)))
+                .part(PartParser::new(PartBehavior::UntilAnnotation(
+                    "End".to_string(),
+                ))),
+            Sink::new("@Case").part(PartParser::new(PartBehavior::UntilNewline)),
+        ])
+        .collect_tokens_from_input("@Test name\n@Case 5\n@End\n5 + 5")
+        .unwrap();
+
+        assert_eq!(
+            text(&blocks),
+            "@Test\n  [0] \" name\"\n  [1] \"\\n@End\"\n  @Case\n    [0] \" 5\\n\"\n<root> \"\\n5 + 5\"\n"
+        );
+    }
+
+    #[test]
+    fn modifiers() {
+        let blocks = Collector::new(vec![
+            Sink::new("@Test").part(PartParser::new(PartBehavior::UntilNewline)),
+            Sink::new("@Skip").modifier(),
+            Sink::new("@Tag")
+                .modifier()
+                .part(PartParser::new(PartBehavior::TokenCount(1))),
+        ])
+        .collect_tokens_from_input("@Skip @Tag slow @Test 5\n")
+        .unwrap();
+
+        assert_eq!(
+            text(&blocks),
+            "<root> \"  \"\n@Test\n  modifier @Skip\n  modifier @Tag\n    [0] \" slow\"\n  [0] \" 5\\n\"\n"
+        );
+    }
+}
@@ -0,0 +1,192 @@
+use std::collections::HashMap;
+
+use garnish_lang_compiler::lex::{lex, LexerToken};
+
+use crate::collector::Collector;
+use crate::visit::iter_depth_first;
+
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum TokenClass {
+    /// Garnish code outside of any annotation, including annotations without a sink.
+    Root,
+    /// Annotation token matched by a sink, at any depth.
+    Annotation(String),
+    /// Token of a part of a top level block.
+    Part { sink: String, part: usize },
+    /// Token of a part of a block nested `depth` levels within a top level block.
+    Nested {
+        sink: String,
+        part: usize,
+        depth: usize,
+    },
+}
+
+impl Collector {
+    /// Classifies each token the way [`Collector::collect_tokens`] would sort it.
+    /// The result has one entry per token, in the same order.
+    pub fn classify_tokens(&self, tokens: &[LexerToken]) -> Result<Vec<TokenClass>, String> {
+        let blocks = self.collect_tokens(tokens)?;
+
+        let mut classes = HashMap::new();
+        for (depth, block) in iter_depth_first(&blocks) {
+            if let Some(token) = block.annotation_token() {
+                classes.insert(
+                    (token.get_line(), token.get_column()),
+                    TokenClass::Annotation(block.annotation_text().clone()),
+                );
+            }
+            for (part, tokens) in block.parts().iter().enumerate() {
+                let class = match depth {
+                    0 => TokenClass::Part {
+                        sink: block.annotation_text().clone(),
+                        part,
+                    },
+                    depth => TokenClass::Nested {
+                        sink: block.annotation_text().clone(),
+                        part,
+                        depth,
+                    },
+                };
+                for token in tokens {
+                    classes.insert((token.get_line(), token.get_column()), class.clone());
+                }
+            }
+        }
+
+        Ok(tokens
+            .iter()
+            .map(|token| {
+                classes
+                    .remove(&(token.get_line(), token.get_column()))
+                    .unwrap_or(TokenClass::Root)
+            })
+            .collect())
+    }
+
+    /// Same as [`Collector::classify_tokens`], aligned with the lexer output for `input`.
+    pub fn classify_input(&self, input: &str) -> Result<Vec<TokenClass>, String> {
+        let tokens = lex(input)?;
+        self.classify_tokens(&tokens)
+    }
+}
+
+#[cfg(test)]
+mod classifying {
+    use garnish_lang_compiler::lex::TokenType;
+
+    use crate::{Collector, PartBehavior, PartParser, Sink, TokenClass};
+
+    fn collector() -> Collector {
+        Collector::new(vec![
+            Sink::new("@Test")
+                .part(PartParser::new(PartBehavior::TokenCount(1)))
+                .part(PartParser::new(PartBehavior::UntilToken(
+                    TokenType::EndExpression,
+                ))),
+            Sink::new("@Skip"),
+        ])
+    }
+
+    fn part(part: usize) -> TokenClass {
+        TokenClass::Part {
+            sink: "@Test".to_string(),
+            part,
+        }
+    }
+
+    fn nested(part: usize) -> TokenClass {
+        TokenClass::Nested {
+            sink: "@Test".to_string(),
+            part,
+            depth: 1,
+        }
+    }
+
+    #[test]
+    fn root_annotation_and_parts() {
+        let classes = collector()
+            .classify_input("5\n@Test t {1}\n@Other")
+            .unwrap();
+
+        assert_eq!(
+            classes,
+            vec![
+                TokenClass::Root,
+                TokenClass::Root,
+                TokenClass::Annotation("@Test".to_string()),
+                part(0),
+                part(0),
+                part(1),
+                part(1),
+                part(1),
+                part(1),
+                TokenClass::Root,
+                TokenClass::Root,
+            ]
+        );
+    }
+
+    #[test]
+    fn nested_blocks() {
+        let classes = collector()
+            .classify_input("@Test a { @Skip @Test b {2} }")
+            .unwrap();
+
+        assert_eq!(
+            classes,
+            vec![
+                TokenClass::Annotation("@Test".to_string()),
+                part(0),
+                part(0),
+                part(1),
+                part(1),
+                part(1),
+                TokenClass::Annotation("@Skip".to_string()),
+                part(1),
+                TokenClass::Annotation("@Test".to_string()),
+                nested(0),
+                nested(0),
+                nested(1),
+                nested(1),
+                nested(1),
+                nested(1),
+                part(1),
+                part(1),
+            ]
+        );
+    }
+
+    #[test]
+    fn aligned_with_lexer_output() {
+        let input = "@Test t { 5 + 5 }\n10";
+
+        let classes = collector().classify_input(input).unwrap();
+
+        assert_eq!(
+            classes.len(),
+            garnish_lang_compiler::lex::lex(input).unwrap().len()
+        );
+    }
+
+    #[test]
+    fn modifiers_classified_in_place() {
+        let modifiers = Collector::new(vec![
+            Sink::new("@Test")
+                .part(PartParser::new(PartBehavior::TokenCount(1)))
+                .part(PartParser::new(PartBehavior::UntilToken(
+                    TokenType::EndExpression,
+                ))),
+            Sink::new("@Skip").modifier(),
+        ]);
+
+        for input in [
+            "@Skip @Skip @Test t {1}",
+            "@Test a { 1 @Skip  @Skip @Test b {2} }",
+        ] {
+            assert_eq!(
+                modifiers.classify_input(input).unwrap(),
+                collector().classify_input(input).unwrap()
+            );
+        }
+    }
+}
@@ -1,18 +1,44 @@
+use std::collections::{BTreeMap, HashMap};
+
 use garnish_lang_compiler::lex::{lex, LexerToken, TokenType};
+use garnish_lang_compiler::parse::{parse, ParseResult};
+
+use crate::diagnostic::{Diagnostic, Label};
+use crate::span::position_after;
+use crate::visit::iter_depth_first;
 
 #[derive(Debug, Eq, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum PartBehavior {
     UntilNewline,
     TokenCount(usize),
-    StartEnd { start: TokenType, end: TokenType },
-    UntilToken(TokenType),
+    StartEnd {
+        #[cfg_attr(feature = "serde", serde(with = "crate::serde_support::token_type"))]
+        start: TokenType,
+        #[cfg_attr(feature = "serde", serde(with = "crate::serde_support::token_type"))]
+        end: TokenType,
+    },
+    UntilToken(
+        #[cfg_attr(feature = "serde", serde(with = "crate::serde_support::token_type"))] TokenType,
+    ),
     UntilAnnotation(String),
+    /// Ends after the next complete garnish expression, following groupings across lines and
+    /// continuing past an operand when the next non whitespace token is a binary operator.
+    Expression,
 }
 
 #[derive(Debug, Eq, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(deny_unknown_fields))]
 pub struct PartParser {
     behavior: PartBehavior,
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, with = "crate::serde_support::token_types")
+    )]
     trim_tokens: Vec<TokenType>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    parse: bool,
 }
 
 impl PartParser {
@@ -20,23 +46,75 @@ impl PartParser {
         PartParser {
             behavior,
             trim_tokens: vec![],
+            parse: false,
         }
     }
+
+    /// Runs the part's tokens through the garnish parser once collected,
+    /// see [`TokenBlock::parsed_part`].
+    pub fn parsed(mut self) -> Self {
+        self.parse = true;
+        self
+    }
 }
 
 #[derive(Debug, Eq, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(deny_unknown_fields))]
 pub struct Sink {
     annotation_text: String,
+    #[cfg_attr(
+        feature = "serde",
+        serde(
+            default = "default_ignore_list",
+            with = "crate::serde_support::token_types"
+        )
+    )]
     ignore_for_end_condition_list: Vec<TokenType>,
+    #[cfg_attr(feature = "serde", serde(default))]
     part_parsers: Vec<PartParser>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    target: bool,
+    #[cfg_attr(feature = "serde", serde(default))]
+    modifier: bool,
+    #[cfg_attr(feature = "serde", serde(default))]
+    observe: bool,
+    #[cfg_attr(
+        feature = "serde",
+        serde(
+            default,
+            deserialize_with = "crate::serde_support::annotation_names::deserialize"
+        )
+    )]
+    allowed_parents: Option<Vec<String>>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(
+            default,
+            deserialize_with = "crate::serde_support::annotation_names::deserialize"
+        )
+    )]
+    allowed_children: Option<Vec<String>>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    top_level_only: bool,
+}
+
+fn default_ignore_list() -> Vec<TokenType> {
+    vec![TokenType::Whitespace]
 }
 
 impl Sink {
     pub fn new<T: ToString>(annotation_text: T) -> Self {
         Self {
             annotation_text: annotation_text.to_string(),
-            ignore_for_end_condition_list: vec![TokenType::Whitespace],
+            ignore_for_end_condition_list: default_ignore_list(),
             part_parsers: vec![],
+            target: false,
+            modifier: false,
+            observe: false,
+            allowed_parents: None,
+            allowed_children: None,
+            top_level_only: false,
         }
     }
 
@@ -44,25 +122,156 @@ impl Sink {
         self.part_parsers.push(part_parser);
         self
     }
+
+    pub fn annotation_text(&self) -> &String {
+        &self.annotation_text
+    }
+
+    /// Captures the statement following the annotation and its parts as the block's
+    /// [`TokenBlock::target`]. Target tokens are only observed, they stay where they
+    /// would otherwise be collected.
+    pub fn target(mut self) -> Self {
+        self.target = true;
+        self
+    }
+
+    /// Groups blocks of this sink onto the next block of a non modifier sink, when only
+    /// whitespace and other modifiers separate them. See [`TokenBlock::modifiers`].
+    pub fn modifier(mut self) -> Self {
+        self.modifier = true;
+        self
+    }
+
+    /// Copies the tokens of this sink's blocks instead of taking them, leaving them in the root
+    /// blocks or enclosing part they would be in without a sink. See [`TokenBlock::observed`].
+    pub fn observe(mut self) -> Self {
+        self.observe = true;
+        self
+    }
+
+    /// Only allows this sink's blocks directly within blocks of the given annotations,
+    /// never at the top level. Breaking this fails collection, or is reported when recovering.
+    pub fn allowed_parents<I: IntoIterator<Item = T>, T: ToString>(mut self, parents: I) -> Self {
+        self.allowed_parents = Some(parents.into_iter().map(|p| p.to_string()).collect());
+        self
+    }
+
+    /// Only allows blocks of the given annotations directly within this sink's blocks.
+    /// Modifiers are allowed anywhere, as they're grouped onto the block they precede.
+    pub fn allowed_children<I: IntoIterator<Item = T>, T: ToString>(mut self, children: I) -> Self {
+        self.allowed_children = Some(children.into_iter().map(|c| c.to_string()).collect());
+        self
+    }
+
+    /// Doesn't allow this sink's blocks within any other block.
+    pub fn top_level_only(mut self) -> Self {
+        self.top_level_only = true;
+        self
+    }
+
+    // Problem with a block of this sink starting at `annotation` within a block of `parent`
+    fn nesting_problem(
+        &self,
+        parent: Option<&Sink>,
+        annotation: &LexerToken,
+    ) -> Option<Diagnostic> {
+        let message = match (parent, &self.allowed_parents) {
+            (Some(parent), _) if self.top_level_only => Some(format!(
+                "{} cannot be inside {}, it must be at the top level",
+                self.annotation_text, parent.annotation_text
+            )),
+            // Modifiers end up grouped onto a block, so they aren't children themselves
+            (Some(parent), _)
+                if !self.modifier
+                    && parent
+                        .allowed_children
+                        .as_ref()
+                        .map(|children| !children.contains(&self.annotation_text))
+                        .unwrap_or(false) =>
+            {
+                Some(format!(
+                    "{} cannot be inside {}",
+                    self.annotation_text, parent.annotation_text
+                ))
+            }
+            (parent, Some(parents))
+                if !parent
+                    .map(|parent| parents.contains(&parent.annotation_text))
+                    .unwrap_or(false) =>
+            {
+                Some(format!(
+                    "{} must be inside {}",
+                    self.annotation_text,
+                    parents.join(" or ")
+                ))
+            }
+            _ => None,
+        };
+
+        message
+            .map(|message| Diagnostic::new(message, annotation.get_line(), annotation.get_column()))
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+enum Mode {
+    Lenient,
+    Strict,
+    Recover,
+}
+
+impl Mode {
+    // Fails on a problem in strict mode, or records it to continue when recovering
+    fn report(
+        self,
+        diagnostics: &mut Vec<Diagnostic>,
+        diagnostic: Diagnostic,
+    ) -> Result<(), Diagnostic> {
+        match self {
+            Mode::Lenient => Ok(()),
+            Mode::Strict => Err(diagnostic),
+            Mode::Recover => {
+                diagnostics.push(diagnostic);
+                Ok(())
+            }
+        }
+    }
+
+    // Same as report, though also failing when lenient
+    fn enforce(
+        self,
+        diagnostics: &mut Vec<Diagnostic>,
+        diagnostic: Diagnostic,
+    ) -> Result<(), Diagnostic> {
+        match self {
+            Mode::Lenient => Err(diagnostic),
+            mode => mode.report(diagnostics, diagnostic),
+        }
+    }
 }
 
 struct CollectionData<'a> {
     sink: &'a Sink,
+    annotation: LexerToken,
     block: TokenBlock,
     nested_level: usize,
     count: usize,
+    // Unmatched start tokens in the current part for PartBehavior::StartEnd and Expression
+    depth: usize,
     ended: bool,
     current_part: usize,
     current_part_tokens: Vec<LexerToken>,
 }
 
 impl<'a> CollectionData<'a> {
-    fn new(sink: &'a Sink, block: TokenBlock, nested_level: usize) -> Self {
+    fn new(sink: &'a Sink, annotation: &LexerToken, nested_level: usize) -> Self {
         Self {
             sink,
-            block,
-            nested_level: nested_level,
+            annotation: annotation.clone(),
+            block: TokenBlock::from_annotation_token(annotation),
+            nested_level,
             count: 0,
+            depth: 0,
             ended: false,
             current_part: 0,
             current_part_tokens: vec![],
@@ -71,6 +280,8 @@ impl<'a> CollectionData<'a> {
 }
 
 #[derive(Debug, Eq, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(deny_unknown_fields))]
 pub struct Collector {
     sinks: Vec<Sink>,
 }
@@ -80,21 +291,180 @@ impl Collector {
         Self { sinks }
     }
 
-    pub fn collect_tokens(&self, tokens: &Vec<LexerToken>) -> Result<Vec<TokenBlock>, String> {
+    pub fn sinks(&self) -> &Vec<Sink> {
+        &self.sinks
+    }
+
+    pub fn into_sinks(self) -> Vec<Sink> {
+        self.sinks
+    }
+
+    /// Parses the parts of `blocks` whose [`PartParser`] was marked [`PartParser::parsed`],
+    /// restoring the parse results left out when blocks are serialized.
+    pub fn parse_blocks(&self, blocks: &mut [TokenBlock]) {
+        for block in blocks.iter_mut() {
+            self.parse_parts(block);
+        }
+    }
+
+    /// Collects leniently, without reporting the problems [`Collector::check_tokens`] fails on
+    /// other than blocks breaking their sink's nesting rules.
+    pub fn collect_tokens(&self, tokens: &[LexerToken]) -> Result<Vec<TokenBlock>, String> {
+        self.collect(tokens, Mode::Lenient)
+            .map(|(blocks, _, _)| blocks)
+            .map_err(|err| err.to_string())
+    }
+
+    pub fn collect_tokens_from_input(&self, input: &str) -> Result<Vec<TokenBlock>, String> {
+        let tokens = lex(input)?;
+        self.collect_tokens(&tokens)
+    }
+
+    /// Collects in strict mode, failing on annotations without a sink, blocks breaking their
+    /// sink's nesting rules, parts left unterminated at the end of input, groupings closed before
+    /// the part collecting them has ended, closing tokens without an opening one,
+    /// [`PartBehavior::StartEnd`] parts not beginning with their start token and parsed parts
+    /// that aren't valid garnish.
+    pub fn check_tokens(&self, tokens: &[LexerToken]) -> Result<Vec<TokenBlock>, Diagnostic> {
+        self.collect(tokens, Mode::Strict)
+            .map(|(blocks, _, _)| blocks)
+    }
+
+    pub fn check_input(&self, input: &str) -> Result<Vec<TokenBlock>, Diagnostic> {
+        let tokens = lex(input).map_err(|err| Diagnostic::new(err, 0, 0))?;
+        self.check_tokens(&tokens)
+    }
+
+    /// Collects while reporting every problem [`Collector::check_tokens`] would fail on.
+    /// Annotations without a sink are reported as warnings, every other problem as an error.
+    /// After a grouping is closed before a part has ended, a closing token has no opening one,
+    /// or a [`PartBehavior::StartEnd`] part begins with something other than its start token,
+    /// open blocks are ended where they are and tokens go to the root until the next top level
+    /// annotation.
+    pub fn recover_tokens(&self, tokens: &[LexerToken]) -> (Vec<TokenBlock>, Vec<Diagnostic>) {
+        match self.collect(tokens, Mode::Recover) {
+            Ok((blocks, _, diagnostics)) => (blocks, diagnostics),
+            Err(diagnostic) => (vec![], vec![diagnostic]),
+        }
+    }
+
+    pub fn recover_input(&self, input: &str) -> (Vec<TokenBlock>, Vec<Diagnostic>) {
+        match lex(input) {
+            Ok(tokens) => self.recover_tokens(&tokens),
+            Err(err) => (vec![], vec![Diagnostic::new(err, 0, 0)]),
+        }
+    }
+
+    /// Same as [`Collector::collect_tokens`], also returning whether any block was still
+    /// collecting when the tokens ran out.
+    pub(crate) fn collect_tokens_open(
+        &self,
+        tokens: &[LexerToken],
+    ) -> Result<(Vec<TokenBlock>, bool), String> {
+        self.collect(tokens, Mode::Lenient)
+            .map(|(blocks, open, _)| (blocks, open))
+            .map_err(|err| err.to_string())
+    }
+
+    /// Whether any sink's blocks depend on tokens outside of their own span, so collecting
+    /// part of an input can give different blocks than collecting all of it.
+    pub(crate) fn reaches_past_blocks(&self) -> bool {
+        self.sinks.iter().any(|sink| sink.target || sink.modifier)
+    }
+
+    pub(crate) fn part_behavior(&self, annotation: &str, part: usize) -> Option<&PartBehavior> {
+        self.sinks
+            .iter()
+            .find(|sink| sink.annotation_text == annotation)
+            .and_then(|sink| sink.part_parsers.get(part))
+            .map(|parser| &parser.behavior)
+    }
+
+    fn collect(
+        &self,
+        tokens: &[LexerToken],
+        mode: Mode,
+    ) -> Result<(Vec<TokenBlock>, bool, Vec<Diagnostic>), Diagnostic> {
         let mut blocks: Vec<TokenBlock> = vec![];
         let mut annotations_stack: Vec<CollectionData> = vec![];
         let mut current_nest_level = 1; // start at 1, reserving 0 for root info in case its needed
+        let mut diagnostics = vec![];
+        let mut resyncing = false;
 
-        for token in tokens.iter() {
+        for (index, token) in tokens.iter().enumerate() {
+            let mut unmatched = false;
+            // Start token a StartEnd part was expected to begin with instead of this one
+            let mut missing_start = None;
             match token.get_token_type() {
                 TokenType::StartExpression | TokenType::StartGroup | TokenType::StartSideEffect => {
                     current_nest_level += 1
                 }
                 TokenType::EndExpression | TokenType::EndGroup | TokenType::EndSideEffect => {
-                    current_nest_level -= 1
+                    match current_nest_level {
+                        // Nothing open to close, stay at the root level
+                        1 => unmatched = true,
+                        _ => current_nest_level -= 1,
+                    }
                 }
                 _ => (), // nothing additional to do
             }
+
+            // A closer at the top level of an expression part belongs to an enclosing grouping,
+            // so the part ends without it and the closer is handled by the enclosing block
+            while let Some(data) = annotations_stack.last_mut() {
+                let in_expression = data
+                    .sink
+                    .part_parsers
+                    .get(data.current_part)
+                    .map(|parser| parser.behavior == PartBehavior::Expression)
+                    .unwrap_or(false);
+                if !(in_expression && data.depth == 0 && is_closer(token)) {
+                    break;
+                }
+
+                mode.report(
+                    &mut diagnostics,
+                    Diagnostic::new(
+                        format!(
+                            "'{}' ends part {} of {} before its expression is complete",
+                            token.get_text(),
+                            data.current_part + 1,
+                            data.annotation.get_text()
+                        ),
+                        token.get_line(),
+                        token.get_column(),
+                    ),
+                )?;
+                data.block
+                    .parts
+                    .push(std::mem::take(&mut data.current_part_tokens));
+                data.current_part += 1;
+                data.ended = data.current_part >= data.sink.part_parsers.len();
+                if !data.ended {
+                    break;
+                }
+
+                let data = annotations_stack.pop().unwrap(); // checked above
+                end_block(data, &mut annotations_stack, &mut blocks);
+            }
+
+            if unmatched && (resyncing || annotations_stack.is_empty()) {
+                mode.report(&mut diagnostics, unmatched_closer(token))?;
+                push_root_token(&mut blocks, token);
+                continue;
+            }
+
+            // After recovering from a problem, tokens stay at the root until a top level annotation
+            if resyncing {
+                match token.get_token_type() == TokenType::Annotation && current_nest_level == 1 {
+                    true => resyncing = false,
+                    false => {
+                        push_root_token(&mut blocks, token);
+                        continue;
+                    }
+                }
+            }
+
             match annotations_stack.last_mut() {
                 None => match token.get_token_type() {
                     TokenType::Annotation => {
@@ -103,38 +473,39 @@ impl Collector {
                             .iter()
                             .find(|item| &item.annotation_text == token.get_text())
                         {
-                            None => (), // No sink for annotation, leave be
-                            Some(sink) => match sink.part_parsers.len() {
-                                0 => blocks
-                                    .push(TokenBlock::with_annotation(token.get_text().clone())),
-                                _ => {
-                                    annotations_stack.push(CollectionData::new(
-                                        sink,
-                                        TokenBlock::with_annotation(token.get_text().clone()),
-                                        current_nest_level
-                                    ));
+                            // No sink for annotation, leave be
+                            None => {
+                                mode.report(&mut diagnostics, unknown_annotation(token, mode))?;
+                                push_root_token(&mut blocks, token)
+                            }
+                            Some(sink) => {
+                                if let Some(problem) = sink.nesting_problem(None, token) {
+                                    mode.enforce(&mut diagnostics, problem)?;
+                                }
+                                match sink.part_parsers.len() {
+                                    0 => blocks.push(TokenBlock::from_annotation_token(token)),
+                                    _ => {
+                                        annotations_stack.push(CollectionData::new(
+                                            sink,
+                                            token,
+                                            current_nest_level,
+                                        ));
+                                    }
                                 }
-                            },
+                            }
                         }
                     }
                     // Not currently collecting annotation tokens
                     // add to root
-                    _ => match blocks.last_mut() {
-                        Some(last) => {
-                            if last.annotation_text.is_empty() {
-                                last.tokens.push(token.clone())
-                            } else {
-                                blocks.push(TokenBlock::with_tokens(vec![token.clone()]))
-                            }
-                        }
-                        None => blocks.push(TokenBlock::with_tokens(vec![token.clone()])),
-                    },
+                    _ => push_root_token(&mut blocks, token),
                 },
                 Some(CollectionData {
                     sink,
+                    annotation,
                     block,
                     nested_level,
                     count,
+                    depth,
                     ended,
                     current_part,
                     current_part_tokens,
@@ -150,16 +521,76 @@ impl Collector {
                                 PartBehavior::UntilNewline => token.get_text().contains("\n"),
                                 PartBehavior::TokenCount(max) => *count >= *max,
                                 PartBehavior::UntilToken(t) => {
-                                    t == &token.get_token_type() && current_nest_level <= *nested_level
+                                    t == &token.get_token_type()
+                                        && current_nest_level <= *nested_level
                                 }
                                 PartBehavior::UntilAnnotation(annotation) => {
                                     token.get_token_type() == TokenType::Annotation
                                         && token.get_text().trim_start_matches('@') == annotation
                                 }
-                                _ => unimplemented!(),
+                                PartBehavior::Expression => {
+                                    let next = tokens[index + 1..].iter().find(|token| {
+                                        token.get_token_type() != TokenType::Whitespace
+                                    });
+                                    expression_step(token, next, depth)
+                                }
+                                PartBehavior::StartEnd { start, end } => {
+                                    let ignored = sink
+                                        .ignore_for_end_condition_list
+                                        .contains(&token.get_token_type());
+                                    if *depth == 0 && token.get_token_type() != *start && !ignored {
+                                        missing_start = Some(*start);
+                                        false
+                                    } else if token.get_token_type() == *end && *depth > 0 {
+                                        *depth -= 1;
+                                        *depth == 0
+                                    } else {
+                                        if token.get_token_type() == *start {
+                                            *depth += 1;
+                                        }
+                                        false
+                                    }
+                                }
                             };
 
+                            let closes_early =
+                                !part_ended && (unmatched || current_nest_level < *nested_level);
+                            if mode != Mode::Lenient && (missing_start.is_some() || closes_early) {
+                                let diagnostic = match (missing_start, unmatched) {
+                                    (Some(start), _) => Diagnostic::new(
+                                        format!(
+                                            "Part {} of {} should start with {:?}, found '{}'",
+                                            *current_part + 1,
+                                            annotation.get_text(),
+                                            start,
+                                            token.get_text()
+                                        ),
+                                        token.get_line(),
+                                        token.get_column(),
+                                    ),
+                                    (None, true) => unmatched_closer(token),
+                                    (None, false) => Diagnostic::new(
+                                        format!(
+                                            "'{}' closes an enclosing grouping before {} has ended",
+                                            token.get_text(),
+                                            annotation.get_text()
+                                        ),
+                                        token.get_line(),
+                                        token.get_column(),
+                                    ),
+                                };
+                                mode.report(&mut diagnostics, diagnostic)?;
+
+                                while let Some(data) = annotations_stack.pop() {
+                                    end_block(data, &mut annotations_stack, &mut blocks);
+                                }
+                                push_root_token(&mut blocks, token);
+                                resyncing = true;
+                                continue;
+                            }
+
                             // Don't add nested annotations to tokens if we have a sink for it
+                            let parent: &Sink = sink;
                             let nested_sink = match token.get_token_type() {
                                 TokenType::Annotation => match self
                                     .sinks
@@ -168,18 +599,36 @@ impl Collector {
                                 {
                                     // No sink for annotation, add to tokens
                                     None => {
+                                        if !part_ended {
+                                            mode.report(
+                                                &mut diagnostics,
+                                                unknown_annotation(token, mode),
+                                            )?;
+                                        }
                                         current_part_tokens.push(token.clone());
                                         None
                                     }
-                                    Some(sink) => match sink.part_parsers.len() {
-                                        0 => {
-                                            blocks.push(TokenBlock::with_annotation(
-                                                token.get_text().clone(),
-                                            ));
-                                            None
+                                    Some(sink) => {
+                                        if let Some(problem) =
+                                            sink.nesting_problem(Some(parent), token)
+                                        {
+                                            mode.enforce(&mut diagnostics, problem)?;
                                         }
-                                        _ => Some(sink),
-                                    },
+                                        let position = NestedPosition::new(
+                                            *current_part,
+                                            current_part_tokens.len(),
+                                        );
+                                        match sink.part_parsers.len() {
+                                            0 => {
+                                                block.nested.push(
+                                                    TokenBlock::from_annotation_token(token)
+                                                        .and_nesting(position),
+                                                );
+                                                None
+                                            }
+                                            _ => Some((sink, position)),
+                                        }
+                                    }
                                 },
                                 _ => {
                                     current_part_tokens.push(token.clone());
@@ -190,29 +639,24 @@ impl Collector {
                             if part_ended {
                                 block.parts.push(current_part_tokens.clone());
                                 *current_part_tokens = vec![];
-                                *current_part = *current_part + 1;
+                                *current_part += 1;
                             }
 
                             *ended = *current_part >= sink.part_parsers.len();
 
                             match nested_sink {
                                 None => (),
-                                Some(sink) => {
-                                    annotations_stack.push(CollectionData::new(
-                                        sink,
-                                        TokenBlock::with_annotation(token.get_text().clone()),
-                                        current_nest_level
-                                    ));
+                                Some((sink, position)) => {
+                                    let mut data =
+                                        CollectionData::new(sink, token, current_nest_level);
+                                    data.block.nesting = Some(position);
+                                    annotations_stack.push(data);
                                 }
                             }
 
                             // Possible to have multiple ended blocks in stack
                             // loop until all have been popped
-                            while annotations_stack
-                                .last()
-                                .and_then(|b| Some(b.ended))
-                                .unwrap_or(false)
-                            {
+                            while annotations_stack.last().map(|b| b.ended).unwrap_or(false) {
                                 let data = annotations_stack.pop().unwrap(); // has to exist to get to this branch
                                 match annotations_stack.last_mut() {
                                     None => blocks.push(data.block),
@@ -226,41 +670,554 @@ impl Collector {
         }
 
         // End all blocks with end of input
-        while let Some(mut data) = annotations_stack.pop() {
-            if data.current_part < data.sink.part_parsers.len() {
-                data.block.parts.push(data.current_part_tokens.clone());
-                data.current_part += 1;
+        let open = !annotations_stack.is_empty();
+        while let Some(data) = annotations_stack.pop() {
+            if mode != Mode::Lenient {
+                if let Some(parser) = data.sink.part_parsers.get(data.current_part) {
+                    if parser.behavior != PartBehavior::UntilNewline {
+                        let (line, column) = tokens
+                            .last()
+                            .map(|last| {
+                                position_after(
+                                    (last.get_line(), last.get_column()),
+                                    last.get_text(),
+                                )
+                            })
+                            .unwrap_or_default();
+                        let diagnostic = Diagnostic::new(
+                            format!(
+                                "Part {} of {} is not terminated before the end of input",
+                                data.current_part + 1,
+                                data.annotation.get_text()
+                            ),
+                            data.annotation.get_line(),
+                            data.annotation.get_column(),
+                        )
+                        .with_label(Label::new(
+                            "part expected to end here",
+                            line,
+                            column,
+                        ));
+                        mode.report(&mut diagnostics, diagnostic)?;
+                    }
+                }
             }
-            match annotations_stack.last_mut() {
-                None => blocks.push(data.block),
-                Some(parent) => parent.block.nested.push(data.block),
+
+            end_block(data, &mut annotations_stack, &mut blocks);
+        }
+
+        if self.sinks.iter().any(|sink| sink.observe) {
+            for block in blocks.iter_mut() {
+                self.observe_nested(block);
+            }
+            blocks = self.observe_root(blocks);
+        }
+
+        if self.sinks.iter().any(|sink| sink.target) {
+            let indexes: HashMap<(usize, usize), usize> = tokens
+                .iter()
+                .enumerate()
+                .map(|(index, token)| ((token.get_line(), token.get_column()), index))
+                .collect();
+            for block in blocks.iter_mut() {
+                self.capture_targets(block, tokens, &indexes);
+            }
+        }
+
+        if self
+            .sinks
+            .iter()
+            .any(|sink| sink.part_parsers.iter().any(|parser| parser.parse))
+        {
+            for block in blocks.iter_mut() {
+                self.parse_parts(block);
+            }
+
+            let errors = iter_depth_first(&blocks)
+                .flat_map(|(_, block)| block.parsed.iter().flatten())
+                .filter_map(|result| result.as_ref().err());
+            for diagnostic in errors {
+                mode.report(&mut diagnostics, diagnostic.clone())?;
+            }
+        }
+
+        if self.sinks.iter().any(|sink| sink.modifier) {
+            for block in blocks.iter_mut() {
+                self.group_nested_modifiers(block);
             }
+            blocks = self.group_root_modifiers(blocks);
         }
 
-        Ok(blocks)
+        Ok((blocks, open, diagnostics))
     }
 
-    pub fn collect_tokens_from_input(&self, input: &str) -> Result<Vec<TokenBlock>, String> {
-        let tokens = lex(input)?;
-        self.collect_tokens(&tokens)
+    fn is_modifier(&self, block: &TokenBlock) -> bool {
+        self.sinks
+            .iter()
+            .any(|sink| sink.modifier && sink.annotation_text == block.annotation_text)
+    }
+
+    // Blocks a run of modifiers can be grouped onto
+    fn is_primary(&self, block: &TokenBlock) -> bool {
+        !block.annotation_text.is_empty() && !self.is_modifier(block) && !block.observed
+    }
+
+    fn is_observed(&self, block: &TokenBlock) -> bool {
+        self.sinks
+            .iter()
+            .any(|sink| sink.observe && sink.annotation_text == block.annotation_text)
+    }
+
+    // Observed blocks are followed by a root block holding copies of their tokens
+    fn observe_root(&self, blocks: Vec<TokenBlock>) -> Vec<TokenBlock> {
+        let mut observed = vec![];
+        for mut block in blocks {
+            match block.annotation_text.is_empty() {
+                true => block
+                    .tokens
+                    .iter()
+                    .for_each(|token| push_root_token(&mut observed, token)),
+                false if self.is_observed(&block) => {
+                    block.observed = true;
+                    let tokens = source_tokens(&block);
+                    observed.push(block);
+                    tokens
+                        .iter()
+                        .for_each(|token| push_root_token(&mut observed, token));
+                }
+                false => observed.push(block),
+            }
+        }
+        observed
+    }
+
+    fn observe_nested(&self, block: &mut TokenBlock) {
+        for child in block.nested.iter_mut() {
+            self.observe_nested(child);
+        }
+
+        // Last child first, so copies only shift children that were already handled
+        for index in (0..block.nested.len()).rev() {
+            if !self.is_observed(&block.nested[index]) {
+                continue;
+            }
+            block.nested[index].observed = true;
+
+            let position = match block.nested[index].nesting {
+                Some(position) if position.part < block.parts.len() => position,
+                _ => continue,
+            };
+            let tokens = source_tokens(&block.nested[index]);
+            let count = tokens.len();
+            block.parts[position.part].splice(position.offset..position.offset, tokens);
+            for later in block.nested[index + 1..].iter_mut() {
+                if let Some(later) = later.nesting.as_mut() {
+                    if later.part == position.part && later.offset >= position.offset {
+                        later.offset += count;
+                    }
+                }
+            }
+        }
+    }
+
+    // Whitespace between grouped modifiers stays in the root, ahead of the block they're on
+    fn group_root_modifiers(&self, blocks: Vec<TokenBlock>) -> Vec<TokenBlock> {
+        let mut grouped = vec![];
+        // Modifiers waiting for a block, and the blocks they came from with whitespace between
+        let mut pending: Vec<TokenBlock> = vec![];
+        let mut originals: Vec<TokenBlock> = vec![];
+
+        for mut block in blocks {
+            if self.is_modifier(&block) {
+                pending.push(block.clone());
+                originals.push(block);
+                continue;
+            }
+
+            if !pending.is_empty() {
+                if block.annotation_text.is_empty() && is_whitespace(&block.tokens) {
+                    originals.push(block);
+                    continue;
+                }
+
+                if self.is_primary(&block) {
+                    block.modifiers = std::mem::take(&mut pending);
+                    grouped.extend(originals.drain(..).filter(|other| !self.is_modifier(other)));
+                } else {
+                    pending.clear();
+                    grouped.append(&mut originals);
+                }
+            }
+
+            grouped.push(block);
+        }
+
+        grouped.append(&mut originals);
+
+        // Whitespace left between modifiers joins the root tokens ahead of them
+        let mut merged: Vec<TokenBlock> = vec![];
+        for block in grouped {
+            match merged.last_mut() {
+                Some(last)
+                    if last.annotation_text.is_empty() && block.annotation_text.is_empty() =>
+                {
+                    last.tokens.extend(block.tokens)
+                }
+                _ => merged.push(block),
+            }
+        }
+        merged
+    }
+
+    fn group_nested_modifiers(&self, block: &mut TokenBlock) {
+        for child in block.nested.iter_mut() {
+            self.group_nested_modifiers(child);
+        }
+
+        let separated_by_whitespace =
+            |first: &TokenBlock, second: &TokenBlock| match (first.nesting, second.nesting) {
+                (Some(first), Some(second)) => {
+                    first.part == second.part
+                        && block
+                            .parts
+                            .get(first.part)
+                            .and_then(|part| part.get(first.offset..second.offset))
+                            .map(is_whitespace)
+                            .unwrap_or(false)
+                }
+                _ => false,
+            };
+
+        let mut grouped: Vec<TokenBlock> = vec![];
+        let mut pending: Vec<TokenBlock> = vec![];
+        for mut child in block.nested.drain(..) {
+            let joined = pending
+                .last()
+                .map(|last| separated_by_whitespace(last, &child))
+                .unwrap_or(false);
+
+            if !joined || !(self.is_modifier(&child) || self.is_primary(&child)) {
+                grouped.append(&mut pending);
+            }
+
+            if self.is_modifier(&child) {
+                pending.push(child);
+            } else {
+                child.modifiers = std::mem::take(&mut pending);
+                grouped.push(child);
+            }
+        }
+        grouped.append(&mut pending);
+
+        block.nested = grouped;
+    }
+
+    fn capture_targets(
+        &self,
+        block: &mut TokenBlock,
+        tokens: &[LexerToken],
+        indexes: &HashMap<(usize, usize), usize>,
+    ) {
+        let is_target = self
+            .sinks
+            .iter()
+            .any(|sink| sink.target && sink.annotation_text == block.annotation_text);
+        let last = block
+            .parts
+            .iter()
+            .flatten()
+            .last()
+            .or(block.annotation.as_ref())
+            .and_then(|token| indexes.get(&(token.get_line(), token.get_column())));
+
+        if let (true, Some(last)) = (is_target, last) {
+            block.target = target_statement(&tokens[last + 1..]);
+        }
+
+        for child in block.nested.iter_mut() {
+            self.capture_targets(child, tokens, indexes);
+        }
+    }
+
+    fn parse_parts(&self, block: &mut TokenBlock) {
+        if let Some(sink) = self
+            .sinks
+            .iter()
+            .find(|sink| sink.annotation_text == block.annotation_text)
+        {
+            block.parsed = block
+                .parts
+                .iter()
+                .zip(sink.part_parsers.iter())
+                .map(|(part, parser)| match parser.parse {
+                    true => Some(parse_part(block.annotation.as_ref(), part)),
+                    false => None,
+                })
+                .collect();
+        }
+
+        for child in block.nested.iter_mut().chain(block.modifiers.iter_mut()) {
+            self.parse_parts(child);
+        }
+    }
+}
+
+// All tokens of a block and its nested blocks in source order
+fn source_tokens(block: &TokenBlock) -> Vec<LexerToken> {
+    let mut tokens = BTreeMap::new();
+    for (_, block) in block.iter_depth_first() {
+        for token in block
+            .annotation_token()
+            .into_iter()
+            .chain(block.parts.iter().flatten())
+            .chain(block.tokens.iter())
+        {
+            tokens.insert((token.get_line(), token.get_column()), token.clone());
+        }
+    }
+    tokens.into_values().collect()
+}
+
+// Ends a block before its parts are done, keeping the part it was collecting
+fn end_block(
+    mut data: CollectionData,
+    annotations_stack: &mut [CollectionData],
+    blocks: &mut Vec<TokenBlock>,
+) {
+    if data.current_part < data.sink.part_parsers.len() {
+        data.block.parts.push(data.current_part_tokens.clone());
+        data.current_part += 1;
+    }
+    match annotations_stack.last_mut() {
+        None => blocks.push(data.block),
+        Some(parent) => parent.block.nested.push(data.block),
+    }
+}
+
+fn push_root_token(blocks: &mut Vec<TokenBlock>, token: &LexerToken) {
+    match blocks.last_mut() {
+        Some(last) if last.annotation_text.is_empty() => last.tokens.push(token.clone()),
+        _ => blocks.push(TokenBlock::with_tokens(vec![token.clone()])),
+    }
+}
+
+// Next complete expression, skipping whitespace and other annotations in front of it
+fn target_statement(tokens: &[LexerToken]) -> Vec<LexerToken> {
+    let start = match tokens.iter().position(|token| {
+        !matches!(
+            token.get_token_type(),
+            TokenType::Whitespace | TokenType::Annotation | TokenType::LineAnnotation
+        )
+    }) {
+        None => return vec![],
+        Some(start) => start,
+    };
+
+    if is_closer(&tokens[start]) {
+        return vec![];
+    }
+
+    let mut depth = 0;
+    let mut target = vec![];
+    for (index, token) in tokens.iter().enumerate().skip(start) {
+        target.push(token.clone());
+        let next = tokens[index + 1..]
+            .iter()
+            .find(|token| token.get_token_type() != TokenType::Whitespace);
+        if expression_step(token, next, &mut depth) {
+            break;
+        }
+    }
+    target
+}
+
+fn is_closer(token: &LexerToken) -> bool {
+    matches!(
+        token.get_token_type(),
+        TokenType::EndExpression | TokenType::EndGroup | TokenType::EndSideEffect
+    )
+}
+
+// Advances an expression part by one token, returning whether the expression ends with it
+fn expression_step(token: &LexerToken, next: Option<&LexerToken>, depth: &mut usize) -> bool {
+    match token.get_token_type() {
+        TokenType::Whitespace | TokenType::Annotation | TokenType::LineAnnotation => false,
+        TokenType::StartExpression | TokenType::StartGroup | TokenType::StartSideEffect => {
+            *depth += 1;
+            false
+        }
+        TokenType::EndExpression | TokenType::EndGroup | TokenType::EndSideEffect => match depth {
+            // Closes a grouping the expression didn't open
+            0 => true,
+            _ => {
+                *depth -= 1;
+                *depth == 0 && !continues_expression(next)
+            }
+        },
+        _ if *depth > 0 => false,
+        t if is_binary_operator(t) || t == TokenType::Not => false,
+        // Operand, or postfix empty apply, at the top level of the expression
+        _ => !continues_expression(next),
+    }
+}
+
+fn continues_expression(next: Option<&LexerToken>) -> bool {
+    next.map(|token| {
+        is_binary_operator(token.get_token_type())
+            || token.get_token_type() == TokenType::EmptyApply
+    })
+    .unwrap_or(false)
+}
+
+fn is_binary_operator(token_type: TokenType) -> bool {
+    matches!(
+        token_type,
+        TokenType::PlusSign
+            | TokenType::Subtraction
+            | TokenType::MultiplicationSign
+            | TokenType::ExponentialSign
+            | TokenType::Division
+            | TokenType::IntegerDivision
+            | TokenType::Remainder
+            | TokenType::Equality
+            | TokenType::Inequality
+            | TokenType::LessThan
+            | TokenType::LessThanOrEqual
+            | TokenType::GreaterThan
+            | TokenType::GreaterThanOrEqual
+            | TokenType::And
+            | TokenType::Or
+            | TokenType::Xor
+            | TokenType::Period
+            | TokenType::Pair
+            | TokenType::Range
+            | TokenType::Comma
+            | TokenType::Apply
+            | TokenType::ApplyTo
+            | TokenType::Reapply
+            | TokenType::Concatenation
+            | TokenType::JumpIfTrue
+            | TokenType::JumpIfFalse
+            | TokenType::ElseJump
+    )
+}
+
+fn parse_part(
+    annotation: Option<&LexerToken>,
+    part: &[LexerToken],
+) -> Result<ParseResult, Diagnostic> {
+    parse(part.to_vec()).map_err(|err| {
+        // Errors are placed at the start of the part's expression
+        let (line, column) = part
+            .iter()
+            .find(|token| token.get_token_type() != TokenType::Whitespace)
+            .or(annotation)
+            .map(|token| (token.get_line(), token.get_column()))
+            .unwrap_or((0, 0));
+        Diagnostic::new(err, line, column)
+    })
+}
+
+fn is_whitespace(tokens: &[LexerToken]) -> bool {
+    tokens
+        .iter()
+        .all(|token| token.get_token_type() == TokenType::Whitespace)
+}
+
+// A warning when recovering, since the annotation is left in place without affecting collection
+fn unknown_annotation(token: &LexerToken, mode: Mode) -> Diagnostic {
+    let diagnostic = match mode {
+        Mode::Recover => Diagnostic::warning::<String>,
+        Mode::Lenient | Mode::Strict => Diagnostic::new::<String>,
+    };
+    diagnostic(
+        format!("No sink for annotation {}", token.get_text()),
+        token.get_line(),
+        token.get_column(),
+    )
+}
+
+fn unmatched_closer(token: &LexerToken) -> Diagnostic {
+    Diagnostic::new(
+        format!("Unmatched '{}'", token.get_text()),
+        token.get_line(),
+        token.get_column(),
+    )
+}
+
+/// Where a nested block was found within its parent, as a part index and the number of
+/// that part's tokens preceding it.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct NestedPosition {
+    part: usize,
+    offset: usize,
+}
+
+impl NestedPosition {
+    pub fn new(part: usize, offset: usize) -> Self {
+        Self { part, offset }
+    }
+
+    pub fn part(&self) -> usize {
+        self.part
+    }
+
+    pub fn offset(&self) -> usize {
+        self.offset
     }
 }
 
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum PartEntry<'a> {
+    Token(&'a LexerToken),
+    NestedBlock(usize),
+    /// Index of a nested block and of one of its modifiers.
+    Modifier(usize, usize),
+}
+
 #[derive(Debug, Eq, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct TokenBlock {
     annotation_text: String,
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, with = "crate::serde_support::optional_token")
+    )]
+    annotation: Option<LexerToken>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    nesting: Option<NestedPosition>,
     nested: Vec<TokenBlock>,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_support::tokens"))]
     tokens: Vec<LexerToken>,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_support::parts"))]
     parts: Vec<Vec<LexerToken>>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, with = "crate::serde_support::tokens")
+    )]
+    target: Vec<LexerToken>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    modifiers: Vec<TokenBlock>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    observed: bool,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    parsed: Vec<Option<Result<ParseResult, Diagnostic>>>,
 }
 
 impl TokenBlock {
     pub fn new(annotation_text: String, tokens: Vec<LexerToken>) -> Self {
         Self {
             annotation_text,
+            annotation: None,
+            nesting: None,
             nested: vec![],
             tokens,
             parts: vec![],
+            target: vec![],
+            modifiers: vec![],
+            observed: false,
+            parsed: vec![],
         }
     }
 
@@ -271,18 +1228,30 @@ impl TokenBlock {
     ) -> Self {
         Self {
             annotation_text,
+            annotation: None,
+            nesting: None,
             nested: vec![],
             tokens,
             parts,
+            target: vec![],
+            modifiers: vec![],
+            observed: false,
+            parsed: vec![],
         }
     }
 
     pub fn with_annotation(annotation_text: String) -> Self {
         Self {
             annotation_text,
+            annotation: None,
+            nesting: None,
             nested: vec![],
             tokens: vec![],
             parts: vec![],
+            target: vec![],
+            modifiers: vec![],
+            observed: false,
+            parsed: vec![],
         }
     }
 
@@ -290,6 +1259,30 @@ impl TokenBlock {
         Self::new("".to_string(), tokens)
     }
 
+    fn from_annotation_token(token: &LexerToken) -> Self {
+        Self {
+            annotation: Some(token.clone()),
+            ..Self::with_annotation(token.get_text().clone())
+        }
+    }
+
+    /// Sets the position of the annotation token this block was collected from.
+    pub fn and_position(mut self, line: usize, column: usize) -> Self {
+        self.annotation = Some(LexerToken::new(
+            self.annotation_text.clone(),
+            TokenType::Annotation,
+            line,
+            column,
+        ));
+        self
+    }
+
+    /// Sets where this block sits within its parent's parts, see [`TokenBlock::nesting`].
+    pub fn and_nesting(mut self, nesting: NestedPosition) -> Self {
+        self.nesting = Some(nesting);
+        self
+    }
+
     pub fn and_children(mut self, children: Vec<TokenBlock>) -> Self {
         self.nested = children;
         self
@@ -304,29 +1297,142 @@ impl TokenBlock {
         &self.annotation_text
     }
 
+    pub fn annotation_token(&self) -> Option<&LexerToken> {
+        self.annotation.as_ref()
+    }
+
+    pub fn annotation_token_mut(&mut self) -> Option<&mut LexerToken> {
+        self.annotation.as_mut()
+    }
+
+    /// Where this block sits within its parent's parts, `None` for root blocks.
+    pub fn nesting(&self) -> Option<NestedPosition> {
+        self.nesting
+    }
+
+    pub fn nesting_mut(&mut self) -> Option<&mut NestedPosition> {
+        self.nesting.as_mut()
+    }
+
     pub fn blocks(&self) -> &Vec<TokenBlock> {
         &self.nested
     }
 
+    pub fn blocks_mut(&mut self) -> &mut Vec<TokenBlock> {
+        &mut self.nested
+    }
+
     pub fn tokens(&self) -> &Vec<LexerToken> {
         &self.tokens
     }
 
+    pub fn tokens_mut(&mut self) -> &mut Vec<LexerToken> {
+        &mut self.tokens
+    }
+
     pub fn tokens_owned(self) -> Vec<LexerToken> {
         self.tokens
     }
 
-    pub fn parts(&self) -> &Vec<Vec<LexerToken>> {
-        &self.parts
+    /// Statement following the annotation when collected by a [`Sink::target`] sink.
+    pub fn target(&self) -> &Vec<LexerToken> {
+        &self.target
     }
-}
 
-#[cfg(test)]
-mod collecting {
-    use garnish_lang_compiler::lex::{LexerToken, TokenType};
+    pub fn target_mut(&mut self) -> &mut Vec<LexerToken> {
+        &mut self.target
+    }
 
-    use crate::collector::{Collector, Sink, TokenBlock};
-    use crate::{PartBehavior, PartParser};
+    /// Blocks of [`Sink::modifier`] sinks directly preceding this one, in document order.
+    /// Whitespace between them is left where it was, in the root or the parent's part.
+    pub fn modifiers(&self) -> &Vec<TokenBlock> {
+        &self.modifiers
+    }
+
+    pub fn modifiers_mut(&mut self) -> &mut Vec<TokenBlock> {
+        &mut self.modifiers
+    }
+
+    /// Whether this block was collected by a [`Sink::observe`] sink. Its tokens are copies of
+    /// ones also found in root blocks or its parent's part, so it isn't rendered.
+    pub fn observed(&self) -> bool {
+        self.observed
+    }
+
+    pub fn and_modifiers(mut self, modifiers: Vec<TokenBlock>) -> Self {
+        self.modifiers = modifiers;
+        self
+    }
+
+    pub fn parts(&self) -> &Vec<Vec<LexerToken>> {
+        &self.parts
+    }
+
+    pub fn parts_mut(&mut self) -> &mut Vec<Vec<LexerToken>> {
+        &mut self.parts
+    }
+
+    /// Parse result of a part whose [`PartParser`] was marked [`PartParser::parsed`].
+    /// Parse results aren't serialized, see [`Collector::parse_blocks`] to restore them.
+    pub fn parsed_part(&self, part: usize) -> Option<&Result<ParseResult, Diagnostic>> {
+        self.parsed.get(part).and_then(Option::as_ref)
+    }
+
+    // Drops a part's parse result once its tokens no longer match it
+    pub(crate) fn forget_parsed_part(&mut self, part: usize) {
+        if let Some(parsed) = self.parsed.get_mut(part) {
+            *parsed = None;
+        }
+    }
+
+    /// Tokens of a part in document order, with the index of each nested block found in
+    /// that part placed where it occurred. Modifiers grouped onto a nested block are placed
+    /// where they occurred too, or just before the block when they have no position.
+    pub fn part_entries(&self, part: usize) -> Vec<PartEntry<'_>> {
+        let tokens = match self.parts.get(part) {
+            None => return vec![],
+            Some(tokens) => tokens,
+        };
+
+        let mut nested: Vec<(usize, PartEntry)> = vec![];
+        for (index, block) in self.nested.iter().enumerate() {
+            for (modifier_index, modifier) in block.modifiers.iter().enumerate() {
+                match modifier.nesting.or(block.nesting) {
+                    Some(position) if position.part == part => {
+                        nested.push((position.offset, PartEntry::Modifier(index, modifier_index)))
+                    }
+                    _ => (),
+                }
+            }
+            match block.nesting {
+                Some(position) if position.part == part => {
+                    nested.push((position.offset, PartEntry::NestedBlock(index)))
+                }
+                _ => (),
+            }
+        }
+        nested.sort_by_key(|(offset, _)| *offset);
+
+        let mut entries = vec![];
+        let mut nested = nested.into_iter().peekable();
+        for (offset, token) in tokens.iter().enumerate() {
+            while let Some((_, entry)) = nested.next_if(|(at, _)| *at <= offset) {
+                entries.push(entry);
+            }
+            entries.push(PartEntry::Token(token));
+        }
+        entries.extend(nested.map(|(_, entry)| entry));
+
+        entries
+    }
+}
+
+#[cfg(test)]
+mod collecting {
+    use garnish_lang_compiler::lex::{LexerToken, TokenType};
+
+    use crate::collector::{Collector, NestedPosition, Sink, TokenBlock};
+    use crate::{render, Diagnostic, PartBehavior, PartEntry, PartParser};
 
     #[test]
     fn single_annotation() {
@@ -338,7 +1444,7 @@ mod collecting {
         assert_eq!(
             blocks,
             vec![
-                TokenBlock::with_annotation("@Test".to_string()),
+                TokenBlock::with_annotation("@Test".to_string()).and_position(0, 0),
                 TokenBlock::with_tokens(vec![
                     LexerToken::new(" ".to_string(), TokenType::Whitespace, 0, 5),
                     LexerToken::new("5".to_string(), TokenType::Number, 0, 6),
@@ -371,7 +1477,8 @@ mod collecting {
                         LexerToken::new("5".to_string(), TokenType::Number, 0, 11),
                     ]
                 ]
-            ),]
+            )
+            .and_position(0, 0),]
         );
     }
 
@@ -399,7 +1506,8 @@ mod collecting {
                         LexerToken::new("5".to_string(), TokenType::Number, 0, 10),
                         LexerToken::new("   \n   ".to_string(), TokenType::Whitespace, 0, 11),
                     ]]
-                ),
+                )
+                .and_position(0, 0),
                 TokenBlock::new_with_parts(
                     "".to_string(),
                     vec![
@@ -442,7 +1550,8 @@ mod collecting {
                         LexerToken::new(" ".to_string(), TokenType::Whitespace, 0, 13),
                         LexerToken::new("5".to_string(), TokenType::Number, 0, 14),
                     ]]
-                ),
+                )
+                .and_position(0, 0),
                 TokenBlock::new(
                     "".to_string(),
                     vec![
@@ -487,7 +1596,8 @@ mod collecting {
                         LexerToken::new(" ".to_string(), TokenType::Whitespace, 0, 13),
                         LexerToken::new("}".to_string(), TokenType::EndExpression, 0, 14),
                     ]]
-                ),
+                )
+                .and_position(0, 0),
                 TokenBlock::new(
                     "".to_string(),
                     vec![
@@ -529,7 +1639,8 @@ mod collecting {
                     LexerToken::new(" ".to_string(), TokenType::Whitespace, 0, 13),
                     LexerToken::new("}".to_string(), TokenType::EndExpression, 0, 14),
                 ]]
-            )]
+            )
+            .and_position(0, 0)]
         );
     }
 
@@ -561,7 +1672,8 @@ mod collecting {
                     LexerToken::new("5".to_string(), TokenType::Number, 0, 15),
                     LexerToken::new("}".to_string(), TokenType::EndExpression, 0, 16),
                 ]]
-            )]
+            )
+            .and_position(0, 0)]
         );
     }
 
@@ -590,7 +1702,8 @@ mod collecting {
                         LexerToken::new(" ".to_string(), TokenType::Whitespace, 0, 11),
                         LexerToken::new("@End".to_string(), TokenType::Annotation, 0, 12),
                     ]]
-                ),
+                )
+                .and_position(0, 0),
                 TokenBlock::new(
                     "".to_string(),
                     vec![
@@ -632,6 +1745,7 @@ mod collecting {
                     LexerToken::new("@End".to_string(), TokenType::Annotation, 3, 0),
                 ]]
             )
+            .and_position(0, 0)
             .and_children(vec![
                 TokenBlock::new_with_parts(
                     "@Case".to_string(),
@@ -643,7 +1757,9 @@ mod collecting {
                         LexerToken::new("10".to_string(), TokenType::Number, 1, 9),
                         LexerToken::new("\n".to_string(), TokenType::Whitespace, 1, 11),
                     ]]
-                ),
+                )
+                .and_position(1, 0)
+                .and_nesting(NestedPosition::new(0, 5)),
                 TokenBlock::new_with_parts(
                     "@Case".to_string(),
                     vec![],
@@ -655,6 +1771,8 @@ mod collecting {
                         LexerToken::new("\n".to_string(), TokenType::Whitespace, 2, 11),
                     ]]
                 )
+                .and_position(2, 0)
+                .and_nesting(NestedPosition::new(0, 5))
             ]),]
         );
     }
@@ -690,22 +1808,818 @@ mod collecting {
                     LexerToken::new("}".to_string(), TokenType::EndExpression, 2, 1),
                 ]]
             )
-            .and_children(vec![
+            .and_position(0, 0)
+            .and_children(vec![TokenBlock::new_with_parts(
+                "@Case".to_string(),
+                vec![],
+                vec![vec![
+                    LexerToken::new(" ".to_string(), TokenType::Whitespace, 1, 5),
+                    LexerToken::new("{".to_string(), TokenType::StartExpression, 1, 6),
+                    LexerToken::new(" ".to_string(), TokenType::Whitespace, 1, 7),
+                    LexerToken::new("10".to_string(), TokenType::Number, 1, 8),
+                    LexerToken::new("+".to_string(), TokenType::PlusSign, 1, 10),
+                    LexerToken::new("10".to_string(), TokenType::Number, 1, 11),
+                    LexerToken::new(" ".to_string(), TokenType::Whitespace, 1, 13),
+                    LexerToken::new("}".to_string(), TokenType::EndExpression, 1, 14),
+                ]]
+            )
+            .and_position(1, 0)
+            .and_nesting(NestedPosition::new(0, 7)),]),]
+        );
+    }
+
+    #[test]
+    fn unmatched_closers() {
+        let collector = Collector::new(vec![Sink::new("@Test").part(PartParser::new(
+            PartBehavior::StartEnd {
+                start: TokenType::StartExpression,
+                end: TokenType::EndExpression,
+            },
+        ))]);
+
+        let blocks = collector.collect_tokens_from_input("} }").unwrap();
+        assert_eq!(render(&blocks), "} }");
+
+        let input = "5 } }\n@Test { 1 }";
+        let blocks = collector.collect_tokens_from_input(input).unwrap();
+        let annotations: Vec<&String> = blocks.iter().map(|b| b.annotation_text()).collect();
+        assert_eq!(annotations, vec!["", "@Test"]);
+        assert_eq!(render(&blocks), input);
+    }
+    #[test]
+    fn recover_from_missing_start() {
+        let collector = Collector::new(vec![Sink::new("@Test")
+            .part(PartParser::new(PartBehavior::TokenCount(1)))
+            .part(PartParser::new(PartBehavior::StartEnd {
+                start: TokenType::StartExpression,
+                end: TokenType::EndExpression,
+            }))]);
+        let input = "@Test a 5\n@Test b { 1 }\n@Test c { 2 }\n";
+        let expected = Diagnostic::new(
+            "Part 2 of @Test should start with StartExpression, found '5'",
+            0,
+            8,
+        );
+
+        let (blocks, diagnostics) = collector.recover_input(input);
+
+        assert_eq!(diagnostics, vec![expected.clone()]);
+        let annotations: Vec<&String> = blocks.iter().map(|b| b.annotation_text()).collect();
+        assert_eq!(annotations, vec!["@Test", "", "@Test", "", "@Test", ""]);
+        assert_eq!(render(&blocks), input);
+        assert_eq!(collector.check_input(input), Err(expected));
+    }
+
+    #[test]
+    fn part_entries_in_document_order() {
+        let input = "@Test 5\n@Case 10\n@Case 20\n@End";
+        let collector = Collector::new(vec![
+            Sink::new("@Test").part(PartParser::new(PartBehavior::UntilAnnotation(
+                "End".to_string(),
+            ))),
+            Sink::new("@Case").part(PartParser::new(PartBehavior::UntilNewline)),
+        ]);
+
+        let blocks = collector.collect_tokens_from_input(input).unwrap();
+
+        assert_eq!(
+            blocks[0].part_entries(0),
+            vec![
+                PartEntry::Token(&LexerToken::new(
+                    " ".to_string(),
+                    TokenType::Whitespace,
+                    0,
+                    5
+                )),
+                PartEntry::Token(&LexerToken::new("5".to_string(), TokenType::Number, 0, 6)),
+                PartEntry::Token(&LexerToken::new(
+                    "\n".to_string(),
+                    TokenType::Whitespace,
+                    0,
+                    7
+                )),
+                PartEntry::NestedBlock(0),
+                PartEntry::NestedBlock(1),
+                PartEntry::Token(&LexerToken::new(
+                    "@End".to_string(),
+                    TokenType::Annotation,
+                    3,
+                    0
+                )),
+            ]
+        );
+    }
+
+    #[test]
+    fn nested_annotation_without_parts() {
+        let input = "@Test 5 @Skip 10 @End";
+        let collector = Collector::new(vec![
+            Sink::new("@Test").part(PartParser::new(PartBehavior::UntilAnnotation(
+                "End".to_string(),
+            ))),
+            Sink::new("@Skip"),
+        ]);
+
+        let blocks = collector.collect_tokens_from_input(input).unwrap();
+
+        assert_eq!(
+            blocks,
+            vec![TokenBlock::new_with_parts(
+                "@Test".to_string(),
+                vec![],
+                vec![vec![
+                    LexerToken::new(" ".to_string(), TokenType::Whitespace, 0, 5),
+                    LexerToken::new("5".to_string(), TokenType::Number, 0, 6),
+                    LexerToken::new(" ".to_string(), TokenType::Whitespace, 0, 7),
+                    LexerToken::new(" ".to_string(), TokenType::Whitespace, 0, 13),
+                    LexerToken::new("10".to_string(), TokenType::Number, 0, 14),
+                    LexerToken::new(" ".to_string(), TokenType::Whitespace, 0, 16),
+                    LexerToken::new("@End".to_string(), TokenType::Annotation, 0, 17),
+                ]]
+            )
+            .and_position(0, 0)
+            .and_children(vec![TokenBlock::with_annotation("@Skip".to_string())
+                .and_position(0, 8)
+                .and_nesting(NestedPosition::new(0, 3))])]
+        );
+    }
+
+    #[test]
+    fn parsed_parts() {
+        let input = "@Test name 5 +\n@Test name 5 + 5";
+        let collector = Collector::new(vec![Sink::new("@Test")
+            .part(PartParser::new(PartBehavior::TokenCount(1)))
+            .part(PartParser::new(PartBehavior::UntilNewline).parsed())]);
+
+        let blocks = collector.collect_tokens_from_input(input).unwrap();
+
+        assert_eq!(blocks[0].parsed_part(0), None);
+        assert_eq!(
+            blocks[0].parsed_part(1),
+            Some(&Err(Diagnostic::new(
+                "Unexpected end of input after +",
+                0,
+                11
+            )))
+        );
+        assert!(blocks[1].parsed_part(1).unwrap().is_ok());
+    }
+
+    fn expression_parts(input: &str) -> Vec<String> {
+        Collector::new(vec![
+            Sink::new("@Expect").part(PartParser::new(PartBehavior::Expression))
+        ])
+        .collect_tokens_from_input(input)
+        .unwrap()
+        .iter()
+        .map(|block| {
+            block
+                .parts()
+                .iter()
+                .chain(std::iter::once(block.tokens()))
+                .flatten()
+                .map(|token| token.get_text().as_str())
+                .collect()
+        })
+        .collect()
+    }
+
+    #[test]
+    fn expression_part() {
+        assert_eq!(
+            expression_parts("@Expect 5 + (3 * 2) 10"),
+            vec![" 5 + (3 * 2)", " 10"]
+        );
+    }
+
+    #[test]
+    fn expression_part_across_lines() {
+        assert_eq!(
+            expression_parts("@Expect {\n  5\n}\n  + 1\nvalue"),
+            vec![" {\n  5\n}\n  + 1", "\nvalue"]
+        );
+        assert_eq!(
+            expression_parts("@Expect -5 +\n  3\n4"),
+            vec![" -5 +\n  3", "\n4"]
+        );
+    }
+
+    #[test]
+    fn expression_part_ends_before_annotation() {
+        assert_eq!(
+            expression_parts("@Expect value\n@Expect 10"),
+            vec![" value", "\n", " 10"]
+        );
+    }
+
+    #[test]
+    fn expression_part_leaves_enclosing_closer() {
+        let input = "{ @Expect } 5";
+        let collector = Collector::new(vec![
+            Sink::new("@Expect").part(PartParser::new(PartBehavior::Expression))
+        ]);
+
+        assert_eq!(expression_parts(input), vec!["{ ", " ", "} 5"]);
+        assert_eq!(
+            collector.check_input(input),
+            Err(Diagnostic::new(
+                "'}' ends part 1 of @Expect before its expression is complete".to_string(),
+                0,
+                10
+            ))
+        );
+        let blocks = collector.collect_tokens_from_input(input).unwrap();
+        assert_eq!(crate::render(&blocks), input);
+
+        // The closer still ends the enclosing block's part
+        let collector = Collector::new(vec![
+            Sink::new("@Test").part(PartParser::new(PartBehavior::StartEnd {
+                start: TokenType::StartExpression,
+                end: TokenType::EndExpression,
+            })),
+            Sink::new("@Expect").part(PartParser::new(PartBehavior::Expression)),
+        ]);
+        let (blocks, diagnostics) = collector.recover_input("@Test { @Expect } 5");
+        assert_eq!(diagnostics.len(), 1);
+        let texts: Vec<&str> = blocks[0].parts()[0]
+            .iter()
+            .map(|token| token.get_text().as_str())
+            .collect();
+        assert_eq!(texts, vec![" ", "{", " ", "}"]);
+        assert_eq!(
+            blocks[0].blocks()[0].parts(),
+            &[vec![LexerToken::new(
+                " ".to_string(),
+                TokenType::Whitespace,
+                0,
+                15
+            )]]
+        );
+        assert_eq!(crate::render(&blocks), "@Test { @Expect } 5");
+    }
+
+    fn target_texts(blocks: &[TokenBlock]) -> Vec<String> {
+        crate::iter_depth_first(blocks)
+            .filter(|(_, block)| !block.annotation_text().is_empty())
+            .map(|(_, block)| {
+                block
+                    .target()
+                    .iter()
+                    .map(|token| token.get_text().as_str())
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn target_statement() {
+        let input = "@Inline\nadd = {\n  $ + 1\n}\n5";
+        let collector = Collector::new(vec![Sink::new("@Inline").target()]);
+
+        let blocks = collector.collect_tokens_from_input(input).unwrap();
+
+        assert_eq!(target_texts(&blocks), vec!["add = {\n  $ + 1\n}"]);
+        // Target tokens remain in the root tokens
+        assert_eq!(blocks.len(), 2);
+        assert_eq!(crate::render(&blocks), input);
+    }
+
+    #[test]
+    fn stacked_targets() {
+        let input = "@Deprecated @Inline value\n10";
+        let collector = Collector::new(vec![
+            Sink::new("@Deprecated").target(),
+            Sink::new("@Inline").target(),
+        ]);
+
+        let blocks = collector.collect_tokens_from_input(input).unwrap();
+
+        assert_eq!(target_texts(&blocks), vec!["value", "value"]);
+    }
+
+    #[test]
+    fn target_within_part() {
+        let input = "@Test { @Inline 5 + 5 } 10";
+        let collector = Collector::new(vec![
+            Sink::new("@Test").part(PartParser::new(PartBehavior::UntilToken(
+                TokenType::EndExpression,
+            ))),
+            Sink::new("@Inline").target(),
+        ]);
+
+        let blocks = collector.collect_tokens_from_input(input).unwrap();
+
+        assert_eq!(target_texts(&blocks), vec!["", "5 + 5"]);
+        assert_eq!(blocks[0].parts()[0].len(), 11);
+    }
+
+    #[test]
+    fn target_missing_at_end_of_grouping() {
+        let input = "{ 5 @Inline }";
+        let collector = Collector::new(vec![Sink::new("@Inline").target()]);
+
+        let blocks = collector.collect_tokens_from_input(input).unwrap();
+
+        assert_eq!(target_texts(&blocks), vec![""]);
+    }
+
+    #[test]
+    fn start_end_part() {
+        let input = "@Test [{5}] 10";
+        let collector = Collector::new(vec![Sink::new("@Test").part(PartParser::new(
+            PartBehavior::StartEnd {
+                start: TokenType::StartSideEffect,
+                end: TokenType::EndSideEffect,
+            },
+        ))]);
+
+        let blocks = collector.collect_tokens_from_input(input).unwrap();
+
+        assert_eq!(
+            blocks,
+            vec![
                 TokenBlock::new_with_parts(
-                    "@Case".to_string(),
+                    "@Test".to_string(),
                     vec![],
                     vec![vec![
-                        LexerToken::new(" ".to_string(), TokenType::Whitespace, 1, 5),
-                        LexerToken::new("{".to_string(), TokenType::StartExpression, 1, 6),
-                        LexerToken::new(" ".to_string(), TokenType::Whitespace, 1, 7),
-                        LexerToken::new("10".to_string(), TokenType::Number, 1, 8),
-                        LexerToken::new("+".to_string(), TokenType::PlusSign, 1, 10),
-                        LexerToken::new("10".to_string(), TokenType::Number, 1, 11),
-                        LexerToken::new(" ".to_string(), TokenType::Whitespace, 1, 13),
-                        LexerToken::new("}".to_string(), TokenType::EndExpression, 1, 14),
+                        LexerToken::new(" ".to_string(), TokenType::Whitespace, 0, 5),
+                        LexerToken::new("[".to_string(), TokenType::StartSideEffect, 0, 6),
+                        LexerToken::new("{".to_string(), TokenType::StartExpression, 0, 7),
+                        LexerToken::new("5".to_string(), TokenType::Number, 0, 8),
+                        LexerToken::new("}".to_string(), TokenType::EndExpression, 0, 9),
+                        LexerToken::new("]".to_string(), TokenType::EndSideEffect, 0, 10),
                     ]]
-                ),
-            ]),]
+                )
+                .and_position(0, 0),
+                TokenBlock::with_tokens(vec![
+                    LexerToken::new(" ".to_string(), TokenType::Whitespace, 0, 11),
+                    LexerToken::new("10".to_string(), TokenType::Number, 0, 12),
+                ])
+            ]
+        );
+    }
+
+    #[test]
+    fn start_end_part_with_nested_pairs() {
+        let input = "@Test {5 {6}} 10";
+        let collector = Collector::new(vec![Sink::new("@Test").part(PartParser::new(
+            PartBehavior::StartEnd {
+                start: TokenType::StartExpression,
+                end: TokenType::EndExpression,
+            },
+        ))]);
+
+        let blocks = collector.collect_tokens_from_input(input).unwrap();
+
+        assert_eq!(blocks[0].parts()[0].len(), 8);
+        assert_eq!(blocks[1].tokens().len(), 2);
+    }
+
+    fn modifier_collector() -> Collector {
+        Collector::new(vec![
+            Sink::new("@Test")
+                .part(PartParser::new(PartBehavior::TokenCount(1)))
+                .part(PartParser::new(PartBehavior::UntilToken(
+                    TokenType::EndExpression,
+                ))),
+            Sink::new("@Skip").modifier(),
+            Sink::new("@Tag")
+                .modifier()
+                .part(PartParser::new(PartBehavior::TokenCount(1))),
+        ])
+    }
+
+    fn annotations(blocks: &[TokenBlock]) -> Vec<&str> {
+        blocks
+            .iter()
+            .map(|block| block.annotation_text().as_str())
+            .collect()
+    }
+
+    #[test]
+    fn modifiers_grouped_onto_block() {
+        let input = "@Skip @Tag slow
+@Test name { 5 }";
+
+        let blocks = modifier_collector()
+            .collect_tokens_from_input(input)
+            .unwrap();
+
+        assert_eq!(annotations(&blocks), vec!["", "@Test"]);
+        assert_eq!(root_texts(&blocks), vec![" \n"]);
+        assert_eq!(blocks[0].tokens().len(), 2);
+        assert_eq!(annotations(blocks[1].modifiers()), vec!["@Skip", "@Tag"]);
+        assert_eq!(blocks[1].modifiers()[1].parts()[0].len(), 2);
+        assert!(blocks[1].modifiers()[1].tokens().is_empty());
+        assert!(no_adjacent_roots(&blocks));
+        assert_eq!(render(&blocks), input);
+    }
+
+    #[test]
+    fn modifiers_leave_parts_unchanged() {
+        let input = "@Test a { 1 @Skip  @Tag slow @Test b {2} }";
+        let plain = Collector::new(vec![
+            modifier_collector().sinks()[0].clone(),
+            Sink::new("@Skip"),
+            Sink::new("@Tag").part(PartParser::new(PartBehavior::TokenCount(1))),
+        ]);
+
+        let blocks = modifier_collector()
+            .collect_tokens_from_input(input)
+            .unwrap();
+        let plain_blocks = plain.collect_tokens_from_input(input).unwrap();
+
+        assert_eq!(blocks[0].parts(), plain_blocks[0].parts());
+    }
+
+    #[test]
+    fn modifiers_not_followed_by_block() {
+        let input = "@Skip 5 @Test name { 5 } @Tag slow";
+
+        let blocks = modifier_collector()
+            .collect_tokens_from_input(input)
+            .unwrap();
+
+        assert_eq!(annotations(&blocks), vec!["@Skip", "", "@Test", "", "@Tag"]);
+        assert!(blocks[2].modifiers().is_empty());
+        assert!(no_adjacent_roots(&blocks));
+        assert_eq!(render(&blocks), input);
+    }
+
+    #[test]
+    fn nested_modifiers() {
+        let input = "@Test a { 1 @Skip @Test b {2} @Test c {3} }";
+
+        let blocks = modifier_collector()
+            .collect_tokens_from_input(input)
+            .unwrap();
+
+        let children = blocks[0].blocks();
+        assert_eq!(annotations(children), vec!["@Test", "@Test"]);
+        assert_eq!(annotations(children[0].modifiers()), vec!["@Skip"]);
+        assert!(children[1].modifiers().is_empty());
+        assert_eq!(
+            children[0].nesting(),
+            Some(NestedPosition { part: 1, offset: 6 })
+        );
+        assert_eq!(
+            children[1].nesting(),
+            Some(NestedPosition { part: 1, offset: 7 })
+        );
+        assert_eq!(
+            children[0].modifiers()[0].nesting(),
+            Some(NestedPosition { part: 1, offset: 5 })
+        );
+        assert_eq!(blocks[0].parts()[1].len(), 9);
+        assert_eq!(render(&blocks), input);
+    }
+
+    fn no_adjacent_roots(blocks: &[TokenBlock]) -> bool {
+        blocks.windows(2).all(|pair| {
+            !pair[0].annotation_text().is_empty() || !pair[1].annotation_text().is_empty()
+        })
+    }
+
+    fn root_texts(blocks: &[TokenBlock]) -> Vec<String> {
+        blocks
+            .iter()
+            .filter(|block| block.annotation_text().is_empty())
+            .map(|block| {
+                block
+                    .tokens()
+                    .iter()
+                    .map(|t| t.get_text().as_str())
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn observed_tokens_stay_in_root() {
+        let input = "5 + 5\n@Doc adds numbers\n10";
+        let collector = Collector::new(vec![Sink::new("@Doc")
+            .observe()
+            .part(PartParser::new(PartBehavior::UntilNewline))]);
+
+        let blocks = collector.collect_tokens_from_input(input).unwrap();
+
+        assert_eq!(blocks.len(), 3);
+        assert!(blocks[1].observed());
+        assert_eq!(blocks[1].parts()[0].len(), 5);
+        assert_eq!(
+            root_texts(&blocks),
+            vec!["5 + 5\n", "@Doc adds numbers\n10"]
+        );
+        assert_eq!(render(&blocks), input);
+    }
+
+    #[test]
+    fn observed_tokens_stay_in_part() {
+        let input = "@Test { @Doc 5 5 } 10";
+        let collector = Collector::new(vec![
+            Sink::new("@Test").part(PartParser::new(PartBehavior::UntilToken(
+                TokenType::EndExpression,
+            ))),
+            Sink::new("@Doc")
+                .observe()
+                .part(PartParser::new(PartBehavior::TokenCount(1))),
+            Sink::new("@Skip"),
+        ]);
+
+        let blocks = collector
+            .collect_tokens_from_input("@Test { @Doc 5 @Skip 5 } 10")
+            .unwrap();
+        let children = blocks[0].blocks();
+        assert!(children[0].observed());
+        assert_eq!(
+            children[1].nesting(),
+            Some(NestedPosition { part: 0, offset: 7 })
+        );
+
+        let blocks = collector.collect_tokens_from_input(input).unwrap();
+        let part: String = blocks[0].parts()[0]
+            .iter()
+            .map(|token| token.get_text().as_str())
+            .collect();
+        assert_eq!(part, " { @Doc 5 5 }");
+        assert_eq!(render(&blocks), input);
+    }
+}
+
+#[cfg(test)]
+mod checking {
+    use garnish_lang_compiler::lex::TokenType;
+
+    use crate::collector::{Collector, Sink};
+    use crate::{render, Diagnostic, Label, PartBehavior, PartParser};
+
+    fn collector() -> Collector {
+        Collector::new(vec![
+            Sink::new("@Test").part(PartParser::new(PartBehavior::UntilAnnotation(
+                "End".to_string(),
+            ))),
+            Sink::new("@Case").part(PartParser::new(PartBehavior::UntilToken(
+                TokenType::EndExpression,
+            ))),
+            Sink::new("@Name").part(PartParser::new(PartBehavior::UntilNewline)),
+        ])
+    }
+
+    #[test]
+    fn valid_input() {
+        let input = "@Test 5\n@Case { 10 }\n@End\n@Name test";
+
+        assert_eq!(
+            collector().check_input(input),
+            Ok(collector().collect_tokens_from_input(input).unwrap())
+        );
+    }
+
+    #[test]
+    fn unknown_annotation_at_root() {
+        assert_eq!(
+            collector().check_input("5 + 5\n@Tset 5"),
+            Err(Diagnostic::new("No sink for annotation @Tset", 1, 0))
+        );
+    }
+
+    #[test]
+    fn unknown_annotation_in_part() {
+        assert_eq!(
+            collector().check_input("@Test 5\n  @Cas { 10 }\n@End"),
+            Err(Diagnostic::new("No sink for annotation @Cas", 1, 2))
+        );
+    }
+
+    #[test]
+    fn unterminated_part() {
+        assert_eq!(
+            collector().check_input("5\n@Test 5\n@Case { 10 }\n"),
+            Err(Diagnostic::new(
+                "Part 1 of @Test is not terminated before the end of input",
+                1,
+                0
+            )
+            .with_label(Label::new("part expected to end here", 3, 0)))
+        );
+    }
+
+    #[test]
+    fn grouping_closed_before_part_ends() {
+        assert_eq!(
+            collector().check_input("{ @Test 5 }\n@End"),
+            Err(Diagnostic::new(
+                "'}' closes an enclosing grouping before @Test has ended",
+                0,
+                10
+            ))
+        );
+    }
+
+    #[test]
+    fn lenient_collection_ignores_problems() {
+        assert!(collector()
+            .collect_tokens_from_input("{ @Test 5 }\n@Tset")
+            .is_ok());
+    }
+
+    #[test]
+    fn part_parse_error() {
+        let collector = Collector::new(vec![
+            Sink::new("@Expect").part(PartParser::new(PartBehavior::UntilNewline).parsed())
+        ]);
+
+        assert_eq!(
+            collector.check_input(
+                "@Expect 5 +
+@Expect 5"
+            ),
+            Err(Diagnostic::new("Unexpected end of input after +", 0, 8))
+        );
+    }
+
+    #[test]
+    fn recover_reports_every_problem() {
+        let (blocks, diagnostics) =
+            collector().recover_input("@Other 5\n@Test 5\n@Case { @Unknown 10 }\n");
+
+        assert_eq!(
+            diagnostics,
+            vec![
+                Diagnostic::warning("No sink for annotation @Other", 0, 0),
+                Diagnostic::warning("No sink for annotation @Unknown", 2, 8),
+                Diagnostic::new(
+                    "Part 1 of @Test is not terminated before the end of input",
+                    1,
+                    0
+                )
+                .with_label(Label::new("part expected to end here", 3, 0)),
+            ]
+        );
+        let annotations: Vec<&String> = blocks.iter().map(|b| b.annotation_text()).collect();
+        assert_eq!(annotations, vec!["", "@Test"]);
+        assert_eq!(blocks[1].blocks()[0].annotation_text(), "@Case");
+    }
+
+    #[test]
+    fn recover_resyncs_at_top_level_annotation() {
+        let input = "{ @Test 5 } 10 { @Name a }\n@Case { 1 }";
+
+        let (blocks, diagnostics) = collector().recover_input(input);
+
+        assert_eq!(
+            diagnostics,
+            vec![Diagnostic::new(
+                "'}' closes an enclosing grouping before @Test has ended",
+                0,
+                10
+            )]
+        );
+        let annotations: Vec<&String> = blocks.iter().map(|b| b.annotation_text()).collect();
+        assert_eq!(annotations, vec!["", "@Test", "", "@Case"]);
+        assert_eq!(render(&blocks), input);
+    }
+
+    #[test]
+    fn unmatched_closers() {
+        assert_eq!(
+            collector().check_input("5 } }\n@Case { 1 }"),
+            Err(Diagnostic::new("Unmatched '}'", 0, 2))
+        );
+        assert_eq!(
+            collector().check_input("@Test 5 ) ) @Case { 2 }"),
+            Err(Diagnostic::new("Unmatched ')'", 0, 8))
+        );
+    }
+
+    #[test]
+    fn recover_unmatched_closers() {
+        let input = "5 } }\n@Case { 1 }";
+
+        let (blocks, diagnostics) = collector().recover_input(input);
+
+        assert_eq!(
+            diagnostics,
+            vec![
+                Diagnostic::new("Unmatched '}'", 0, 2),
+                Diagnostic::new("Unmatched '}'", 0, 4)
+            ]
+        );
+        let annotations: Vec<&String> = blocks.iter().map(|b| b.annotation_text()).collect();
+        assert_eq!(annotations, vec!["", "@Case"]);
+        assert_eq!(render(&blocks), input);
+    }
+
+    #[test]
+    fn recover_unmatched_closers_in_part() {
+        let input = "@Test 5 ) ) @Case { 2 }";
+
+        let (blocks, diagnostics) = collector().recover_input(input);
+
+        assert_eq!(
+            diagnostics,
+            vec![
+                Diagnostic::new("Unmatched ')'", 0, 8),
+                Diagnostic::new("Unmatched ')'", 0, 10)
+            ]
+        );
+        let annotations: Vec<&String> = blocks.iter().map(|b| b.annotation_text()).collect();
+        assert_eq!(annotations, vec!["@Test", "", "@Case"]);
+        assert_eq!(render(&blocks), input);
+    }
+
+    #[test]
+    fn recover_valid_input() {
+        let input = "@Test 5\n@Case { 10 }\n@End\n@Name test";
+
+        assert_eq!(
+            collector().recover_input(input),
+            (
+                collector().collect_tokens_from_input(input).unwrap(),
+                vec![]
+            )
+        );
+    }
+
+    fn nesting_collector() -> Collector {
+        Collector::new(vec![
+            Sink::new("@Test")
+                .top_level_only()
+                .allowed_children(["@Case"])
+                .part(PartParser::new(PartBehavior::UntilToken(
+                    TokenType::EndExpression,
+                ))),
+            Sink::new("@Case")
+                .allowed_parents(["@Test"])
+                .part(PartParser::new(PartBehavior::TokenCount(1))),
+            Sink::new("@Skip"),
+        ])
+    }
+
+    #[test]
+    fn nesting_rules_followed() {
+        assert!(nesting_collector()
+            .check_input("@Skip @Test { @Case 1 @Case 2 }")
+            .is_ok());
+    }
+
+    #[test]
+    fn allowed_parents() {
+        assert_eq!(
+            nesting_collector().check_input("5\n@Case 1"),
+            Err(Diagnostic::new("@Case must be inside @Test", 1, 0))
+        );
+    }
+
+    #[test]
+    fn allowed_children() {
+        assert_eq!(
+            nesting_collector().check_input("@Test { @Case 1 @Skip }"),
+            Err(Diagnostic::new("@Skip cannot be inside @Test", 0, 16))
+        );
+    }
+
+    #[test]
+    fn top_level_only() {
+        let (blocks, diagnostics) = nesting_collector().recover_input("@Test { @Test { 1 } }");
+
+        assert_eq!(
+            diagnostics,
+            vec![Diagnostic::new(
+                "@Test cannot be inside @Test, it must be at the top level",
+                0,
+                8
+            )]
+        );
+        assert_eq!(blocks[0].blocks()[0].annotation_text(), "@Test");
+    }
+
+    #[test]
+    fn nesting_rules_enforced_when_collecting() {
+        assert_eq!(
+            nesting_collector().collect_tokens_from_input("5\n@Case 1"),
+            Err("2:1: @Case must be inside @Test".to_string())
+        );
+    }
+
+    #[test]
+    fn modifiers_allowed_as_children() {
+        let collector = Collector::new(
+            nesting_collector()
+                .into_sinks()
+                .into_iter()
+                .map(|sink| match sink.annotation_text() == "@Skip" {
+                    true => Sink::new("@Skip").modifier(),
+                    false => sink,
+                })
+                .collect(),
+        );
+
+        let blocks = collector.check_input("@Test { @Skip @Case 1 }").unwrap();
+
+        assert_eq!(
+            blocks[0].blocks()[0].modifiers()[0].annotation_text(),
+            "@Skip"
         );
     }
 }
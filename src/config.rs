@@ -0,0 +1,170 @@
+use std::path::Path;
+
+use crate::collector::{Collector, Sink};
+use crate::spec::parse_sink_specs;
+
+/// Reads a collector from JSON of the form `{"sinks": [...]}`, see [`collector_from_toml`].
+pub fn collector_from_json(config: &str) -> Result<Collector, String> {
+    serde_json::from_str(config).map_err(|err| err.to_string())
+}
+
+/// Reads a collector from TOML with one `[[sinks]]` table per sink.
+///
+/// ```toml
+/// [[sinks]]
+/// annotation_text = "@Test"
+/// target = true
+///
+/// [[sinks.part_parsers]]
+/// behavior = { TokenCount = 1 }
+///
+/// [[sinks.part_parsers]]
+/// behavior = { StartEnd = { start = "StartExpression", end = "EndExpression" } }
+/// trim_tokens = ["Whitespace"]
+/// ```
+///
+/// Token types are written by name, and `ignore_for_end_condition_list` defaults to
+/// `["Whitespace"]` as with [`Sink::new`].
+pub fn collector_from_toml(config: &str) -> Result<Collector, String> {
+    toml::from_str(config).map_err(|err| err.to_string())
+}
+
+/// Reads sinks from a config file's contents, choosing the format by the extension of `path`.
+/// `.json` and `.toml` files are read as with [`collector_from_json`] and [`collector_from_toml`],
+/// anything else as one [`parse_sink_specs`] definition per line.
+pub fn sinks_from_config(path: &str, config: &str) -> Result<Vec<Sink>, String> {
+    let collector = match Path::new(path).extension().and_then(|ext| ext.to_str()) {
+        Some("json") => collector_from_json(config),
+        Some("toml") => collector_from_toml(config),
+        _ => return parse_sink_specs(config),
+    };
+
+    collector.map(Collector::into_sinks)
+}
+
+#[cfg(test)]
+mod loading {
+    use garnish_lang_compiler::lex::TokenType;
+
+    use crate::{
+        collector_from_json, collector_from_toml, sinks_from_config, Collector, PartBehavior,
+        PartParser, Sink,
+    };
+
+    fn expected() -> Collector {
+        Collector::new(vec![
+            Sink::new("@Test")
+                .part(PartParser::new(PartBehavior::TokenCount(1)))
+                .part(PartParser::new(PartBehavior::StartEnd {
+                    start: TokenType::StartExpression,
+                    end: TokenType::EndExpression,
+                })),
+            Sink::new("@Skip").modifier(),
+        ])
+    }
+
+    #[test]
+    fn toml() {
+        let config = r#"
+[[sinks]]
+annotation_text = "@Test"
+
+[[sinks.part_parsers]]
+behavior = { TokenCount = 1 }
+
+[[sinks.part_parsers]]
+behavior = { StartEnd = { start = "StartExpression", end = "EndExpression" } }
+
+[[sinks]]
+annotation_text = "@Skip"
+modifier = true
+"#;
+
+        assert_eq!(collector_from_toml(config).unwrap(), expected());
+    }
+
+    #[test]
+    fn json() {
+        let config = r#"{"sinks": [
+            {"annotation_text": "@Test", "part_parsers": [
+                {"behavior": {"TokenCount": 1}},
+                {"behavior": {"StartEnd": {"start": "StartExpression", "end": "EndExpression"}}}
+            ]},
+            {"annotation_text": "@Skip", "modifier": true}
+        ]}"#;
+
+        assert_eq!(collector_from_json(config).unwrap(), expected());
+    }
+
+    #[test]
+    fn unit_behavior() {
+        let config = "[[sinks]]\nannotation_text = \"@Doc\"\n\n[[sinks.part_parsers]]\nbehavior = \"UntilNewline\"\n";
+
+        assert_eq!(
+            collector_from_toml(config).unwrap(),
+            Collector::new(vec![
+                Sink::new("@Doc").part(PartParser::new(PartBehavior::UntilNewline))
+            ])
+        );
+    }
+
+    #[test]
+    fn unknown_token_type() {
+        let config = r#"{"sinks": [{"annotation_text": "@Test", "part_parsers": [
+            {"behavior": {"UntilToken": "Brace"}}
+        ]}]}"#;
+
+        let err = collector_from_json(config).unwrap_err();
+
+        assert!(err.starts_with("Unknown token type 'Brace'"), "{}", err);
+    }
+
+    #[test]
+    fn unknown_field() {
+        let config = "[[sinks]]\nannotation_text = \"@Skip\"\nmodifer = true\n";
+
+        let err = collector_from_toml(config).unwrap_err();
+
+        assert!(err.contains("unknown field `modifer`"), "{}", err);
+    }
+
+    #[test]
+    fn unknown_part_field() {
+        let config = r#"{"sinks": [{"annotation_text": "@Test", "part_parsers": [
+            {"behavior": "UntilNewline", "parsed": true}
+        ]}]}"#;
+
+        let err = collector_from_json(config).unwrap_err();
+
+        assert!(err.contains("unknown field `parsed`"), "{}", err);
+    }
+
+    #[test]
+    fn nesting_names_get_annotation_prefix() {
+        let config = r#"{"sinks": [
+            {"annotation_text": "@Case", "allowed_parents": ["Test", "@Suite"]},
+            {"annotation_text": "@Test", "allowed_children": [" Case"]}
+        ]}"#;
+
+        assert_eq!(
+            collector_from_json(config).unwrap(),
+            Collector::new(vec![
+                Sink::new("@Case").allowed_parents(["@Test", "@Suite"]),
+                Sink::new("@Test").allowed_children(["@Case"]),
+            ])
+        );
+    }
+
+    #[test]
+    fn format_from_extension() {
+        assert_eq!(
+            sinks_from_config("sinks.json", r#"{"sinks": [{"annotation_text": "@End"}]}"#),
+            Ok(vec![Sink::new("@End")])
+        );
+        assert_eq!(
+            sinks_from_config("sinks.txt", "@End"),
+            Ok(vec![Sink::new("@End")])
+        );
+        assert!(sinks_from_config("sinks.toml", "@End").is_err());
+    }
+}
@@ -0,0 +1,384 @@
+use garnish_lang_compiler::lex::{LexerToken, TokenType};
+
+use crate::collector::{Collector, PartBehavior, PartParser, Sink};
+use crate::diagnostic::Diagnostic;
+use crate::spec::{annotation_name, parse_behavior};
+
+/// Annotation declaring a sink from within garnish source, one per line.
+///
+/// ```text
+/// @annotation Test parts(name: count 1, body: braces)
+/// @annotation Skip modifier
+/// @annotation Doc parts(newline) observe
+/// ```
+///
+/// Parts take the same behaviors as [`parse_sink_spec`](crate::parse_sink_spec), with spaces
+/// in place of `:`, an optional `label:` and `braces`, `parens` or `brackets` as shorthand for
+/// `start-end` with the matching token types. Flags `target`, `modifier`, `observe`,
+/// `top-level`, `parent:Name` and `child:Name` follow the parts, as with `parse_sink_spec`.
+pub const DECLARATION: &str = "@annotation";
+
+/// Sink collecting [`DECLARATION`] annotations.
+pub fn declaration_sink() -> Sink {
+    Sink::new(DECLARATION).part(PartParser::new(PartBehavior::UntilNewline))
+}
+
+/// Reads the sinks declared in `input`, in declaration order.
+pub fn declared_sinks(input: &str) -> Result<Vec<Sink>, Diagnostic> {
+    let declarations = declarations(input)?;
+    Ok(declarations.into_iter().map(|(sink, _)| sink).collect())
+}
+
+impl Collector {
+    /// Adds the sinks declared in `input` to this collector's, along with [`declaration_sink`]
+    /// so the declarations themselves are collected. The result can be used for `input` and for
+    /// any files it imports. Declarations within imported files aren't read, as imports aren't
+    /// followed; call this again with each imported file to add its sinks.
+    pub fn with_declarations(&self, input: &str) -> Result<Collector, Diagnostic> {
+        let mut sinks = self.sinks().clone();
+        if !sinks
+            .iter()
+            .any(|sink| sink.annotation_text() == DECLARATION)
+        {
+            sinks.push(declaration_sink());
+        }
+
+        for (sink, annotation) in declarations(input)? {
+            if sinks
+                .iter()
+                .any(|other| other.annotation_text() == sink.annotation_text())
+            {
+                return Err(error(
+                    format!("{} is already defined", sink.annotation_text()),
+                    &annotation,
+                ));
+            }
+            sinks.push(sink);
+        }
+
+        Ok(Collector::new(sinks))
+    }
+}
+
+// Declared sinks with the annotation token declaring them
+fn declarations(input: &str) -> Result<Vec<(Sink, LexerToken)>, Diagnostic> {
+    let blocks = Collector::new(vec![declaration_sink()])
+        .collect_tokens_from_input(input)
+        .map_err(|err| Diagnostic::new(err, 0, 0))?;
+
+    blocks
+        .iter()
+        .filter_map(|block| block.annotation_token().map(|token| (block, token)))
+        .map(|(block, annotation)| {
+            let tokens = block.parts().first().cloned().unwrap_or_default();
+            declaration(&tokens, annotation).map(|sink| (sink, annotation.clone()))
+        })
+        .collect()
+}
+
+fn declaration(tokens: &[LexerToken], annotation: &LexerToken) -> Result<Sink, Diagnostic> {
+    let mut words = tokens
+        .iter()
+        .enumerate()
+        .filter(|(_, token)| token.get_token_type() != TokenType::Whitespace);
+
+    let name = match words.next() {
+        Some((_, token))
+            if matches!(
+                token.get_token_type(),
+                TokenType::Identifier | TokenType::Annotation
+            ) =>
+        {
+            format!("@{}", token.get_text().trim_start_matches('@'))
+        }
+        Some((_, token)) => return Err(error("Expected an annotation name", token)),
+        None => {
+            return Err(error(
+                format!("Expected an annotation name after {}", DECLARATION),
+                annotation,
+            ))
+        }
+    };
+
+    let mut sink = Sink::new(&name);
+    let mut rest = words.peekable();
+    if let Some((index, _)) = rest.next_if(|(_, token)| token.get_text() == "parts") {
+        let (parts, end) = parts(&tokens[index + 1..], &name, &tokens[index])?;
+        for part in parts {
+            sink = sink.part(PartParser::new(part));
+        }
+        // Skip past the closing parenthesis
+        while rest.next_if(|(next, _)| *next <= index + 1 + end).is_some() {}
+    }
+
+    // Tokens not separated by whitespace make up one flag, e.g. `top-level` or `parent:Test`
+    let mut flags: Vec<(String, &LexerToken)> = vec![];
+    let mut previous = None;
+    for (index, token) in rest {
+        match flags.last_mut() {
+            Some((flag, _)) if previous == Some(index - 1) => flag.push_str(token.get_text()),
+            _ => flags.push((token.get_text().clone(), token)),
+        }
+        previous = Some(index);
+    }
+
+    let mut parents = vec![];
+    let mut children = vec![];
+    for (flag, token) in flags {
+        sink = match flag.split_once(':') {
+            Some(("parent", parent)) if !parent.is_empty() => {
+                parents.push(annotation_name(parent));
+                sink
+            }
+            Some(("child", child)) if !child.is_empty() => {
+                children.push(annotation_name(child));
+                sink
+            }
+            _ => match flag.as_str() {
+                "target" => sink.target(),
+                "modifier" => sink.modifier(),
+                "observe" => sink.observe(),
+                "top-level" => sink.top_level_only(),
+                flag => {
+                    return Err(error(
+                        format!("Unknown flag '{}' for {}", flag, name),
+                        token,
+                    ))
+                }
+            },
+        };
+    }
+
+    if !parents.is_empty() {
+        sink = sink.allowed_parents(parents);
+    }
+    if !children.is_empty() {
+        sink = sink.allowed_children(children);
+    }
+
+    Ok(sink)
+}
+
+// Behaviors within `(...)`, and the index of the closing parenthesis in `tokens`
+fn parts(
+    tokens: &[LexerToken],
+    name: &str,
+    keyword: &LexerToken,
+) -> Result<(Vec<PartBehavior>, usize), Diagnostic> {
+    let start = tokens
+        .iter()
+        .position(|token| token.get_token_type() != TokenType::Whitespace)
+        .filter(|start| tokens[*start].get_token_type() == TokenType::StartGroup)
+        .ok_or_else(|| error(format!("Expected '(' after parts of {}", name), keyword))?;
+
+    let mut behaviors = vec![];
+    let mut entry: Vec<&LexerToken> = vec![];
+    let mut depth = 0;
+    for (index, token) in tokens.iter().enumerate().skip(start + 1) {
+        match token.get_token_type() {
+            TokenType::StartGroup => depth += 1,
+            TokenType::EndGroup if depth > 0 => depth -= 1,
+            TokenType::EndGroup | TokenType::Comma if depth == 0 => {
+                behaviors.push(behavior(&entry, name, token)?);
+                entry.clear();
+                if token.get_token_type() == TokenType::EndGroup {
+                    return Ok((behaviors, index));
+                }
+                continue;
+            }
+            _ => (),
+        }
+        entry.push(token);
+    }
+
+    Err(error(
+        format!("Expected ')' to close parts of {}", name),
+        &tokens[start],
+    ))
+}
+
+fn behavior(
+    entry: &[&LexerToken],
+    name: &str,
+    end: &LexerToken,
+) -> Result<PartBehavior, Diagnostic> {
+    let words: Vec<&LexerToken> = entry
+        .iter()
+        .filter(|token| token.get_token_type() != TokenType::Whitespace)
+        .copied()
+        .collect();
+
+    // Drop a leading `label:`
+    let words = match words.get(1) {
+        Some(token) if token.get_text() == ":" => &words[2..],
+        _ => &words[..],
+    };
+    let first = match words.first() {
+        Some(first) => *first,
+        None => return Err(error(format!("Expected a part for {}", name), end)),
+    };
+
+    // Whitespace between words separates the pieces of a spec behavior
+    let start = entry.iter().position(|token| std::ptr::eq(*token, first));
+    let mut spec = String::new();
+    for token in entry[start.unwrap_or_default()..].iter() {
+        match token.get_token_type() {
+            TokenType::Whitespace => spec.push(':'),
+            _ => spec.push_str(token.get_text()),
+        }
+    }
+    let spec = match spec.trim_end_matches(':') {
+        "braces" => "start-end:StartExpression:EndExpression",
+        "parens" => "start-end:StartGroup:EndGroup",
+        "brackets" => "start-end:StartSideEffect:EndSideEffect",
+        spec => spec,
+    };
+
+    parse_behavior(spec).map_err(|err| error(err, first))
+}
+
+fn error<T: ToString>(message: T, token: &LexerToken) -> Diagnostic {
+    Diagnostic::new(message, token.get_line(), token.get_column())
+}
+
+#[cfg(test)]
+mod declaring {
+    use garnish_lang_compiler::lex::TokenType;
+
+    use crate::{
+        declaration_sink, declared_sinks, Collector, Diagnostic, PartBehavior, PartParser, Sink,
+    };
+
+    #[test]
+    fn parts_and_flags() {
+        let sinks = declared_sinks(
+            "@annotation Test parts(name: count 1, body: braces)\n@annotation Skip modifier\n@annotation Doc parts(newline) observe target\n5",
+        )
+        .unwrap();
+
+        assert_eq!(
+            sinks,
+            vec![
+                Sink::new("@Test")
+                    .part(PartParser::new(PartBehavior::TokenCount(1)))
+                    .part(PartParser::new(PartBehavior::StartEnd {
+                        start: TokenType::StartExpression,
+                        end: TokenType::EndExpression,
+                    })),
+                Sink::new("@Skip").modifier(),
+                Sink::new("@Doc")
+                    .part(PartParser::new(PartBehavior::UntilNewline))
+                    .observe()
+                    .target(),
+            ]
+        );
+    }
+
+    #[test]
+    fn nesting_flags() {
+        let sinks = declared_sinks(
+            "@annotation Test parts(braces) top-level child:Case child:@Skip\n@annotation Case parent:Test",
+        )
+        .unwrap();
+
+        assert_eq!(
+            sinks,
+            vec![
+                Sink::new("@Test")
+                    .part(PartParser::new(PartBehavior::StartEnd {
+                        start: TokenType::StartExpression,
+                        end: TokenType::EndExpression,
+                    }))
+                    .top_level_only()
+                    .allowed_children(["@Case", "@Skip"]),
+                Sink::new("@Case").allowed_parents(["@Test"]),
+            ]
+        );
+        assert_eq!(
+            declared_sinks("@annotation Case parent:"),
+            Err(Diagnostic::new("Unknown flag 'parent:' for @Case", 0, 17))
+        );
+    }
+
+    #[test]
+    fn spec_behaviors() {
+        let sinks = declared_sinks(
+            "@annotation Suite parts(token EndExpression, annotation End, start-end StartGroup EndGroup)",
+        )
+        .unwrap();
+
+        assert_eq!(
+            sinks,
+            vec![Sink::new("@Suite")
+                .part(PartParser::new(PartBehavior::UntilToken(
+                    TokenType::EndExpression
+                )))
+                .part(PartParser::new(PartBehavior::UntilAnnotation(
+                    "End".to_string()
+                )))
+                .part(PartParser::new(PartBehavior::StartEnd {
+                    start: TokenType::StartGroup,
+                    end: TokenType::EndGroup,
+                }))]
+        );
+    }
+
+    #[test]
+    fn declared_sinks_applied() {
+        let input = "@annotation Test parts(name: count 1, body: braces)\n@Test add { 5 + 5 }\n";
+
+        let collector = Collector::new(vec![]).with_declarations(input).unwrap();
+        let blocks = collector.check_input(input).unwrap();
+
+        let annotations: Vec<&String> = blocks.iter().map(|b| b.annotation_text()).collect();
+        assert_eq!(annotations, vec!["@annotation", "@Test", ""]);
+        assert_eq!(collector.sinks()[0], declaration_sink());
+    }
+
+    #[test]
+    fn already_defined() {
+        let result = Collector::new(vec![Sink::new("@Test")])
+            .with_declarations("5\n@annotation Test parts(newline)");
+
+        assert_eq!(
+            result,
+            Err(Diagnostic::new("@Test is already defined", 1, 0))
+        );
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            declared_sinks("@annotation Test parts(count many)"),
+            Err(Diagnostic::new("Invalid token count 'many'", 0, 23))
+        );
+        assert_eq!(
+            declared_sinks("@annotation Test parts(token Brace)"),
+            Err(Diagnostic::new("Unknown token type 'Brace'", 0, 23))
+        );
+        assert_eq!(
+            declared_sinks("@annotation Test parts(newline, )"),
+            Err(Diagnostic::new("Expected a part for @Test", 0, 32))
+        );
+        assert_eq!(
+            declared_sinks("@annotation Test parts(newline"),
+            Err(Diagnostic::new(
+                "Expected ')' to close parts of @Test",
+                0,
+                22
+            ))
+        );
+        assert_eq!(
+            declared_sinks("@annotation Test hidden"),
+            Err(Diagnostic::new("Unknown flag 'hidden' for @Test", 0, 17))
+        );
+        assert_eq!(
+            declared_sinks("@annotation\n"),
+            Err(Diagnostic::new(
+                "Expected an annotation name after @annotation",
+                0,
+                0
+            ))
+        );
+    }
+}
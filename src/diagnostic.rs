@@ -0,0 +1,267 @@
+use std::fmt::{Display, Formatter};
+
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum DiagnosticSeverity {
+    Error,
+    Warning,
+}
+
+impl Display for DiagnosticSeverity {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DiagnosticSeverity::Error => write!(f, "error"),
+            DiagnosticSeverity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+/// Secondary position of a [`Diagnostic`], with a note on its part in the problem.
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct Label {
+    message: String,
+    line: usize,
+    column: usize,
+}
+
+impl Label {
+    pub fn new<T: ToString>(message: T, line: usize, column: usize) -> Self {
+        Self {
+            message: message.to_string(),
+            line,
+            column,
+        }
+    }
+
+    pub fn message(&self) -> &String {
+        &self.message
+    }
+
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    pub fn column(&self) -> usize {
+        self.column
+    }
+}
+
+/// A problem found while collecting, positioned at a zero based line and column.
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct Diagnostic {
+    message: String,
+    line: usize,
+    column: usize,
+    severity: DiagnosticSeverity,
+    labels: Vec<Label>,
+}
+
+impl Diagnostic {
+    pub fn new<T: ToString>(message: T, line: usize, column: usize) -> Self {
+        Self {
+            message: message.to_string(),
+            line,
+            column,
+            severity: DiagnosticSeverity::Error,
+            labels: vec![],
+        }
+    }
+
+    pub fn warning<T: ToString>(message: T, line: usize, column: usize) -> Self {
+        Self {
+            severity: DiagnosticSeverity::Warning,
+            ..Self::new(message, line, column)
+        }
+    }
+
+    pub fn with_label(mut self, label: Label) -> Self {
+        self.labels.push(label);
+        self
+    }
+
+    pub fn message(&self) -> &String {
+        &self.message
+    }
+
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    pub fn column(&self) -> usize {
+        self.column
+    }
+
+    pub fn severity(&self) -> DiagnosticSeverity {
+        self.severity
+    }
+
+    pub fn labels(&self) -> &Vec<Label> {
+        &self.labels
+    }
+}
+
+impl Display for Diagnostic {
+    /// Formats as `line:column: message` with one based line and column.
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}: {}", self.line + 1, self.column + 1, self.message)
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum DiagnosticStyle {
+    Plain,
+    Ansi,
+}
+
+const RED: &str = "\x1b[1;31m";
+const YELLOW: &str = "\x1b[1;33m";
+const BLUE: &str = "\x1b[1;34m";
+const BOLD: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+
+/// Renders a diagnostic for `source` read from `file`, showing each line it points at with the
+/// primary position underlined with `^` and labels with `-`. Underlines span the word starting
+/// at their position.
+pub fn render_diagnostic(
+    diagnostic: &Diagnostic,
+    source: &str,
+    file: &str,
+    style: DiagnosticStyle,
+) -> String {
+    let paint = |color: &str, text: &str| match style {
+        DiagnosticStyle::Plain => text.to_string(),
+        DiagnosticStyle::Ansi => format!("{}{}{}", color, text, RESET),
+    };
+    let color = match diagnostic.severity {
+        DiagnosticSeverity::Error => RED,
+        DiagnosticSeverity::Warning => YELLOW,
+    };
+    let severity = diagnostic.severity.to_string();
+
+    // Primary mark first so it's drawn closest to its line
+    let mut marks = vec![(diagnostic.line, diagnostic.column, '^', "", color)];
+    for label in diagnostic.labels.iter() {
+        marks.push((label.line, label.column, '-', label.message.as_str(), BLUE));
+    }
+    let mut lines: Vec<usize> = marks.iter().map(|mark| mark.0).collect();
+    lines.sort_unstable();
+    lines.dedup();
+
+    let source_lines: Vec<&str> = source.split('\n').collect();
+    let width = (lines.last().copied().unwrap_or_default() + 1)
+        .to_string()
+        .len();
+    let gutter = |number: &str| paint(BLUE, &format!("{:>width$} |", number, width = width));
+
+    let mut output = format!(
+        "{}{}\n",
+        paint(color, &format!("{}:", severity)),
+        paint(BOLD, &format!(" {}", diagnostic.message))
+    );
+    output.push_str(&format!(
+        "{}{} {}:{}:{}\n",
+        " ".repeat(width),
+        paint(BLUE, "-->"),
+        file,
+        diagnostic.line + 1,
+        diagnostic.column + 1
+    ));
+    output.push_str(&format!("{}\n", gutter("")));
+
+    for (index, line) in lines.iter().enumerate() {
+        if index > 0 && lines[index - 1] + 1 < *line {
+            output.push_str(&format!("{}\n", paint(BLUE, "...")));
+        }
+
+        let text = source_lines
+            .get(*line)
+            .map(|text| text.trim_end_matches('\r'))
+            .unwrap_or_default();
+        output.push_str(&format!("{} {}\n", gutter(&(line + 1).to_string()), text));
+
+        for (_, column, mark, message, mark_color) in marks.iter().filter(|m| m.0 == *line) {
+            let length = text
+                .chars()
+                .skip(*column)
+                .take_while(|c| !c.is_whitespace())
+                .count()
+                .max(1);
+            let underline = format!("{} {}", mark.to_string().repeat(length), message);
+            output.push_str(&format!(
+                "{} {}{}\n",
+                gutter(""),
+                " ".repeat(*column),
+                paint(mark_color, underline.trim_end())
+            ));
+        }
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod rendering_diagnostics {
+    use garnish_lang_compiler::lex::TokenType;
+
+    use crate::{
+        render_diagnostic, Collector, Diagnostic, DiagnosticStyle, Label, PartBehavior, PartParser,
+        Sink,
+    };
+
+    #[test]
+    fn unterminated_part() {
+        let input = "5\n@Test name { 5 + 5\n10";
+        let collector = Collector::new(vec![Sink::new("@Test")
+            .part(PartParser::new(PartBehavior::TokenCount(1)))
+            .part(PartParser::new(PartBehavior::StartEnd {
+                start: TokenType::StartExpression,
+                end: TokenType::EndExpression,
+            }))]);
+
+        let diagnostic = collector.check_input(input).unwrap_err();
+
+        assert_eq!(
+            render_diagnostic(&diagnostic, input, "a.garnish", DiagnosticStyle::Plain),
+            "error: Part 2 of @Test is not terminated before the end of input
+ --> a.garnish:2:1
+  |
+2 | @Test name { 5 + 5
+  | ^^^^^
+3 | 10
+  |   - part expected to end here
+"
+        );
+    }
+
+    #[test]
+    fn separated_lines() {
+        let diagnostic = Diagnostic::warning("Unused annotation", 0, 2).with_label(Label::new(
+            "declared here",
+            9,
+            0,
+        ));
+        let input = "1 @Old\n\n\n\n\n\n\n\n\n@annotation Old";
+
+        assert_eq!(
+            render_diagnostic(&diagnostic, input, "b.garnish", DiagnosticStyle::Plain),
+            "warning: Unused annotation
+  --> b.garnish:1:3
+   |
+ 1 | 1 @Old
+   |   ^^^^
+...
+10 | @annotation Old
+   | ----------- declared here
+"
+        );
+    }
+
+    #[test]
+    fn ansi() {
+        let diagnostic = Diagnostic::new("Bad", 0, 0);
+
+        let output = render_diagnostic(&diagnostic, "@A", "c.garnish", DiagnosticStyle::Ansi);
+
+        assert!(output.starts_with("\x1b[1;31merror:\x1b[0m\x1b[1m Bad\x1b[0m\n"));
+        assert!(output.contains("\x1b[1;31m^^\x1b[0m"));
+    }
+}
@@ -0,0 +1,732 @@
+use garnish_lang_compiler::lex::{lex, LexerToken, TokenType};
+
+use crate::collector::{NestedPosition, PartEntry, TokenBlock};
+use crate::incremental::move_position;
+use crate::span::{position_after, Span};
+
+// Blocks are addressed by index paths, the first index into the root list
+// followed by an index into the nested blocks of each level.
+
+pub fn block_at<'a>(blocks: &'a [TokenBlock], path: &[usize]) -> Option<&'a TokenBlock> {
+    let (first, rest) = path.split_first()?;
+    rest.iter().try_fold(blocks.get(*first)?, |block, index| {
+        block.blocks().get(*index)
+    })
+}
+
+pub fn block_at_mut<'a>(
+    blocks: &'a mut [TokenBlock],
+    path: &[usize],
+) -> Option<&'a mut TokenBlock> {
+    let (first, rest) = path.split_first()?;
+    rest.iter()
+        .try_fold(blocks.get_mut(*first)?, |block, index| {
+            block.blocks_mut().get_mut(*index)
+        })
+}
+
+/// Removes a block and everything nested in it, moving the tokens after it back to where
+/// it started. Whitespace left on either side is joined the same as lexing would.
+pub fn remove_block(blocks: &mut Vec<TokenBlock>, path: &[usize]) -> Result<TokenBlock, String> {
+    let span = block_at(blocks, path).and_then(TokenBlock::span);
+    let (removed, parent_path) = match path.split_last() {
+        None => return Err(no_block(path)),
+        Some((index, [])) => {
+            if *index >= blocks.len() {
+                return Err(no_block(path));
+            }
+            (blocks.remove(*index), None)
+        }
+        Some((index, parent_path)) => {
+            let parent = block_at_mut(blocks, parent_path).ok_or_else(|| no_block(path))?;
+            if *index >= parent.blocks().len() {
+                return Err(no_block(path));
+            }
+            (parent.blocks_mut().remove(*index), Some(parent_path))
+        }
+    };
+
+    if let Some(span) = span {
+        // Whitespace between a root block's modifiers is left in the root tokens
+        for root in blocks
+            .iter_mut()
+            .filter(|block| block.annotation_text().is_empty())
+        {
+            root.tokens_mut()
+                .retain(|token| !span.contains(token.get_line(), token.get_column()));
+        }
+        for block in blocks.iter_mut() {
+            shift_block(block, span.end(), span.start());
+        }
+    }
+
+    let position = removed
+        .modifiers()
+        .first()
+        .and_then(TokenBlock::nesting)
+        .or(removed.nesting());
+    match (
+        parent_path.and_then(|path| block_at_mut(blocks, path)),
+        position,
+    ) {
+        (Some(parent), Some(position)) => join_part_whitespace(parent, position),
+        (Some(_), None) => (),
+        (None, _) => regroup_root_tokens(blocks),
+    }
+    Ok(removed)
+}
+
+/// Replaces the tokens of a part, positioning them where the part starts and moving the tokens
+/// after it. Blocks nested within the replaced part are removed with it, along with any parse
+/// result of the part.
+pub fn replace_part(
+    blocks: &mut [TokenBlock],
+    path: &[usize],
+    part: usize,
+    mut tokens: Vec<LexerToken>,
+) -> Result<(), String> {
+    let block = block_at_mut(blocks, path).ok_or_else(|| no_block(path))?;
+    if part >= block.parts().len() {
+        return Err(format!("Block at {:?} has no part {}", path, part));
+    }
+
+    let extent = part_extent(block, part);
+    let start = extent
+        .map(|span| span.start())
+        .unwrap_or_else(|| part_start(block, part));
+    let end = extent.map(|span| span.end()).unwrap_or(start);
+    let mut position = start;
+    for token in tokens.iter_mut() {
+        place_token(token, &mut position);
+    }
+
+    block.parts_mut()[part].clear();
+    block
+        .blocks_mut()
+        .retain(|child| child.nesting().map(|p| p.part()) != Some(part));
+    block.forget_parsed_part(part);
+    for existing in blocks.iter_mut() {
+        shift_block(existing, end, position);
+    }
+
+    if let Some(block) = block_at_mut(blocks, path) {
+        block.parts_mut()[part] = tokens;
+    }
+    Ok(())
+}
+
+/// Same as [`replace_part`] with the replacement lexed from text.
+pub fn replace_part_text(
+    blocks: &mut [TokenBlock],
+    path: &[usize],
+    part: usize,
+    text: &str,
+) -> Result<(), String> {
+    replace_part(blocks, path, part, lex(text)?)
+}
+
+// Source covered by a part's tokens and the blocks nested in it
+fn part_extent(block: &TokenBlock, part: usize) -> Option<Span> {
+    block
+        .blocks()
+        .iter()
+        .filter(|child| child.nesting().map(|p| p.part()) == Some(part))
+        .filter_map(TokenBlock::span)
+        .fold(block.part_span(part), |extent, span| match extent {
+            None => Some(span),
+            Some(extent) => Some(extent.merge(&span)),
+        })
+}
+
+// Where an empty part sits, after the parts before it or the annotation
+fn part_start(block: &TokenBlock, part: usize) -> (usize, usize) {
+    (0..part)
+        .rev()
+        .find_map(|previous| part_extent(block, previous))
+        .map(|span| span.end())
+        .or_else(|| {
+            block
+                .annotation_token()
+                .map(|token| Span::of_token(token).end())
+        })
+        .unwrap_or((0, 0))
+}
+
+/// Inserts a block before the one at `path`, separated from it by whitespace matching
+/// the target's indentation when it starts a line, or a single space otherwise.
+/// The inserted tokens are positioned where they're rendered, moving the tokens after them.
+pub fn insert_before(
+    blocks: &mut Vec<TokenBlock>,
+    path: &[usize],
+    mut block: TokenBlock,
+) -> Result<(), String> {
+    let target = block_at(blocks, path).ok_or_else(|| no_block(path))?;
+    let start = target.span().map(|span| span.start()).unwrap_or((0, 0));
+    // Modifiers are rendered ahead of the block, so insert before them
+    let target_position = target
+        .modifiers()
+        .first()
+        .and_then(TokenBlock::nesting)
+        .or(target.nesting());
+
+    let previous = match path.split_last() {
+        None => return Err(no_block(path)),
+        Some((index, [])) => blocks[..*index]
+            .iter()
+            .rev()
+            .find(|previous| previous.annotation_text().is_empty())
+            .and_then(|previous| {
+                previous
+                    .tokens()
+                    .iter()
+                    .rev()
+                    .find(|token| (token.get_line(), token.get_column()) < start)
+            }),
+        Some((_, parent_path)) => match (block_at(blocks, parent_path), target_position) {
+            (Some(parent), Some(position)) => position
+                .offset()
+                .checked_sub(1)
+                .and_then(|offset| parent.parts()[position.part()].get(offset)),
+            _ => None,
+        },
+    };
+    let mut end = start;
+    place_block(&mut block, &mut end);
+    let separator = separator_token(previous, end);
+    let after = position_after(end, separator.get_text());
+
+    for existing in blocks.iter_mut() {
+        shift_block(existing, start, after);
+    }
+
+    match path.split_last() {
+        None => Err(no_block(path)),
+        Some((index, [])) => {
+            blocks.insert(*index, block);
+            blocks.insert(*index + 1, TokenBlock::with_tokens(vec![separator]));
+            regroup_root_tokens(blocks);
+            Ok(())
+        }
+        Some((index, parent_path)) => {
+            let parent = block_at_mut(blocks, parent_path).ok_or_else(|| no_block(path))?;
+            let position = match target_position {
+                None => {
+                    parent.blocks_mut().insert(*index, block);
+                    return Ok(());
+                }
+                Some(position) => position,
+            };
+
+            parent.parts_mut()[position.part()].insert(position.offset(), separator);
+            move_offsets(parent, position, |offset| offset + 1);
+
+            parent
+                .blocks_mut()
+                .insert(*index, block.and_nesting(position));
+            Ok(())
+        }
+    }
+}
+
+// Moves nested blocks and their modifiers at or after `position` within its part
+fn move_offsets(parent: &mut TokenBlock, position: NestedPosition, moved: fn(usize) -> usize) {
+    for child in parent.blocks_mut().iter_mut() {
+        for modifier in child.modifiers_mut().iter_mut() {
+            move_offset(modifier, position, moved);
+        }
+        move_offset(child, position, moved);
+    }
+}
+
+fn move_offset(block: &mut TokenBlock, position: NestedPosition, moved: fn(usize) -> usize) {
+    if let Some(p) = block.nesting_mut() {
+        if p.part() == position.part() && p.offset() >= position.offset() {
+            *p = NestedPosition::new(p.part(), moved(p.offset()));
+        }
+    }
+}
+
+// Joins the part tokens either side of a removed block when nothing else sits between them
+fn join_part_whitespace(parent: &mut TokenBlock, position: NestedPosition) {
+    let occupied = parent.blocks().iter().any(|child| {
+        child
+            .modifiers()
+            .iter()
+            .chain(std::iter::once(child))
+            .any(|block| block.nesting() == Some(position))
+    });
+    if !occupied && join_whitespace(&mut parent.parts_mut()[position.part()], position.offset()) {
+        move_offsets(parent, position, |offset| offset - 1);
+    }
+}
+
+// Joins whitespace tokens either side of `index` into one, returning whether they were joined
+fn join_whitespace(tokens: &mut Vec<LexerToken>, index: usize) -> bool {
+    if index == 0 || index >= tokens.len() {
+        return false;
+    }
+    let (previous, next) = (&tokens[index - 1], &tokens[index]);
+    let end = position_after(
+        (previous.get_line(), previous.get_column()),
+        previous.get_text(),
+    );
+    if previous.get_token_type() != TokenType::Whitespace
+        || next.get_token_type() != TokenType::Whitespace
+        || end != (next.get_line(), next.get_column())
+    {
+        return false;
+    }
+
+    tokens[index - 1] = LexerToken::new(
+        format!("{}{}", previous.get_text(), next.get_text()),
+        TokenType::Whitespace,
+        previous.get_line(),
+        previous.get_column(),
+    );
+    tokens.remove(index);
+    true
+}
+
+enum Slot {
+    Token(usize),
+    Nested(usize),
+    Modifier(usize, usize),
+}
+
+// Positions a block's tokens in the order they're rendered, starting at `position` and
+// leaving it after the last one. A block without an annotation token is given one.
+fn place_block(block: &mut TokenBlock, position: &mut (usize, usize)) {
+    for modifier in block.modifiers_mut().iter_mut() {
+        place_block(modifier, position);
+    }
+    if block.observed() {
+        return;
+    }
+
+    if block.annotation_token().is_none() && !block.annotation_text().is_empty() {
+        let placed = std::mem::replace(block, TokenBlock::with_tokens(vec![]));
+        *block = placed.and_position(position.0, position.1);
+    }
+    if let Some(token) = block.annotation_token_mut() {
+        place_token(token, position);
+    }
+
+    for part in 0..block.parts().len() {
+        // Entries by index, as the part can't stay borrowed while placing them
+        let mut offset = 0;
+        let slots: Vec<Slot> = block
+            .part_entries(part)
+            .into_iter()
+            .map(|entry| match entry {
+                PartEntry::Token(_) => {
+                    offset += 1;
+                    Slot::Token(offset - 1)
+                }
+                PartEntry::NestedBlock(index) => Slot::Nested(index),
+                PartEntry::Modifier(index, modifier) => Slot::Modifier(index, modifier),
+            })
+            .collect();
+
+        for slot in slots {
+            match slot {
+                Slot::Token(offset) => place_token(&mut block.parts_mut()[part][offset], position),
+                Slot::Nested(index) => place_nested(&mut block.blocks_mut()[index], position),
+                Slot::Modifier(index, modifier) => place_block(
+                    &mut block.blocks_mut()[index].modifiers_mut()[modifier],
+                    position,
+                ),
+            }
+        }
+    }
+
+    // Nested blocks not placed within a part follow the parts
+    let parts = block.parts().len();
+    for child in block.blocks_mut().iter_mut().filter(|child| {
+        child
+            .nesting()
+            .map(|position| position.part() >= parts)
+            .unwrap_or(true)
+    }) {
+        place_block(child, position);
+    }
+
+    for token in block.tokens_mut().iter_mut() {
+        place_token(token, position);
+    }
+}
+
+// A nested block within a part, its modifiers having been placed at their own entries
+fn place_nested(block: &mut TokenBlock, position: &mut (usize, usize)) {
+    let modifiers = std::mem::take(block.modifiers_mut());
+    place_block(block, position);
+    *block.modifiers_mut() = modifiers;
+}
+
+fn place_token(token: &mut LexerToken, position: &mut (usize, usize)) {
+    *token = LexerToken::new(
+        token.get_text().clone(),
+        token.get_token_type(),
+        position.0,
+        position.1,
+    );
+    *position = position_after(*position, token.get_text());
+}
+
+// Moves every token at or after `from` so that `from` lands on `to`
+fn shift_block(block: &mut TokenBlock, from: (usize, usize), to: (usize, usize)) {
+    if let Some(token) = block.annotation_token_mut() {
+        shift_token(token, from, to);
+    }
+    for token in block.parts_mut().iter_mut().flatten() {
+        shift_token(token, from, to);
+    }
+    for token in block.tokens_mut().iter_mut() {
+        shift_token(token, from, to);
+    }
+    for token in block.target_mut().iter_mut() {
+        shift_token(token, from, to);
+    }
+    for modifier in block.modifiers_mut().iter_mut() {
+        shift_block(modifier, from, to);
+    }
+    for child in block.blocks_mut().iter_mut() {
+        shift_block(child, from, to);
+    }
+}
+
+fn shift_token(token: &mut LexerToken, from: (usize, usize), to: (usize, usize)) {
+    let current = (token.get_line(), token.get_column());
+    if current >= from {
+        let (line, column) = move_position(current, from, to);
+        *token = LexerToken::new(
+            token.get_text().clone(),
+            token.get_token_type(),
+            line,
+            column,
+        );
+    }
+}
+
+fn separator_token(previous: Option<&LexerToken>, (line, column): (usize, usize)) -> LexerToken {
+    let text = previous
+        .filter(|token| token.get_token_type() == TokenType::Whitespace)
+        .and_then(|token| token.get_text().rfind('\n').map(|i| &token.get_text()[i..]))
+        .unwrap_or(" ");
+    LexerToken::new(text.to_string(), TokenType::Whitespace, line, column)
+}
+
+// Puts root tokens back where collection leaves them, ahead of the first annotated block
+// starting after them, with whitespace runs joined
+fn regroup_root_tokens(blocks: &mut Vec<TokenBlock>) {
+    let position = |token: &LexerToken| (token.get_line(), token.get_column());
+    let mut tokens: Vec<LexerToken> = blocks
+        .iter()
+        .filter(|block| block.annotation_text().is_empty())
+        .flat_map(|block| block.tokens().iter().cloned())
+        .collect();
+    tokens.sort_by_key(position);
+
+    let mut tokens = tokens.into_iter().peekable();
+    let annotated: Vec<TokenBlock> = std::mem::take(blocks)
+        .into_iter()
+        .filter(|block| !block.annotation_text().is_empty())
+        .collect();
+    for block in annotated {
+        if let Some(start) = block.annotation_token().map(position) {
+            let before = std::iter::from_fn(|| tokens.next_if(|token| position(token) < start));
+            push_root_tokens(blocks, before.collect());
+        }
+        blocks.push(block);
+    }
+    push_root_tokens(blocks, tokens.collect());
+}
+
+fn push_root_tokens(blocks: &mut Vec<TokenBlock>, tokens: Vec<LexerToken>) {
+    if tokens.is_empty() {
+        return;
+    }
+
+    let mut joined: Vec<LexerToken> = vec![];
+    for token in tokens {
+        let last = joined.len();
+        joined.push(token);
+        join_whitespace(&mut joined, last);
+    }
+    blocks.push(TokenBlock::with_tokens(joined));
+}
+
+// Keep root token blocks from sitting next to each other, as they would after collection
+pub(crate) fn merge_root_tokens(blocks: &mut Vec<TokenBlock>, index: usize) {
+    if index == 0 || index >= blocks.len() {
+        return;
+    }
+
+    if blocks[index - 1].annotation_text().is_empty() && blocks[index].annotation_text().is_empty()
+    {
+        let next = blocks.remove(index);
+        let mut tokens = blocks[index - 1].tokens().clone();
+        let joined = tokens.len();
+        tokens.extend(next.tokens_owned());
+        join_whitespace(&mut tokens, joined);
+        blocks[index - 1] = TokenBlock::with_tokens(tokens);
+    }
+}
+
+fn no_block(path: &[usize]) -> String {
+    format!("No block at {:?}", path)
+}
+
+#[cfg(test)]
+mod editing {
+    use garnish_lang_compiler::lex::TokenType;
+
+    use crate::{
+        insert_before, remove_block, render, replace_part_text, Collector, PartBehavior,
+        PartParser, Sink, Span, TokenBlock,
+    };
+
+    fn collector() -> Collector {
+        Collector::new(vec![
+            Sink::new("@Test")
+                .part(PartParser::new(PartBehavior::TokenCount(1)))
+                .part(PartParser::new(PartBehavior::UntilToken(
+                    TokenType::EndExpression,
+                ))),
+            Sink::new("@Case")
+                .part(PartParser::new(PartBehavior::TokenCount(1)))
+                .part(PartParser::new(PartBehavior::UntilToken(
+                    TokenType::EndExpression,
+                ))),
+            Sink::new("@Deprecated"),
+        ])
+    }
+
+    const INPUT: &str = "value = 5\n\n@Test first {\n    @Case one { 1 }\n    @Case two { 2 }\n}\n\n@Test second { 5 }\n";
+
+    #[test]
+    fn rename_test() {
+        let mut blocks = collector().collect_tokens_from_input(INPUT).unwrap();
+
+        replace_part_text(&mut blocks, &[1], 0, " renamed").unwrap();
+
+        assert_eq!(
+            render(&blocks),
+            "value = 5\n\n@Test renamed {\n    @Case one { 1 }\n    @Case two { 2 }\n}\n\n@Test second { 5 }\n"
+        );
+        assert_eq!(
+            blocks,
+            collector()
+                .collect_tokens_from_input(&render(&blocks))
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn replaced_part_moves_following_tokens() {
+        let mut blocks = collector().collect_tokens_from_input(INPUT).unwrap();
+
+        replace_part_text(&mut blocks, &[1], 1, " {\n\n 10 }").unwrap();
+
+        assert_eq!(
+            blocks,
+            collector()
+                .collect_tokens_from_input(&render(&blocks))
+                .unwrap()
+        );
+        assert_eq!(blocks[3].span(), Some(Span::new((6, 0), (6, 18))));
+    }
+
+    #[test]
+    fn replacing_part_removes_nested_blocks() {
+        let mut blocks = collector().collect_tokens_from_input(INPUT).unwrap();
+
+        replace_part_text(&mut blocks, &[1], 1, " { 10 }").unwrap();
+
+        assert!(blocks[1].blocks().is_empty());
+        assert_eq!(
+            render(&blocks),
+            "value = 5\n\n@Test first { 10 }\n\n@Test second { 5 }\n"
+        );
+        assert_eq!(
+            blocks,
+            collector()
+                .collect_tokens_from_input(&render(&blocks))
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn remove_nested_block() {
+        let mut blocks = collector().collect_tokens_from_input(INPUT).unwrap();
+
+        let removed = remove_block(&mut blocks, &[1, 0]).unwrap();
+
+        assert_eq!(removed.annotation_text(), "@Case");
+        assert_eq!(
+            render(&blocks),
+            "value = 5\n\n@Test first {\n    \n    @Case two { 2 }\n}\n\n@Test second { 5 }\n"
+        );
+        assert_eq!(
+            blocks,
+            collector()
+                .collect_tokens_from_input(&render(&blocks))
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn remove_root_block_merges_tokens() {
+        let mut blocks = collector().collect_tokens_from_input(INPUT).unwrap();
+
+        remove_block(&mut blocks, &[1]).unwrap();
+
+        assert_eq!(blocks.len(), 3);
+        assert_eq!(render(&blocks), "value = 5\n\n\n\n@Test second { 5 }\n");
+        assert_eq!(
+            blocks,
+            collector()
+                .collect_tokens_from_input(&render(&blocks))
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn insert_before_root_block() {
+        let mut blocks = collector().collect_tokens_from_input(INPUT).unwrap();
+
+        insert_before(
+            &mut blocks,
+            &[3],
+            TokenBlock::with_annotation("@Deprecated".to_string()),
+        )
+        .unwrap();
+
+        assert_eq!(
+            render(&blocks),
+            "value = 5\n\n@Test first {\n    @Case one { 1 }\n    @Case two { 2 }\n}\n\n@Deprecated\n@Test second { 5 }\n"
+        );
+        assert_eq!(
+            blocks,
+            collector()
+                .collect_tokens_from_input(&render(&blocks))
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn insert_before_nested_block() {
+        let mut blocks = collector().collect_tokens_from_input(INPUT).unwrap();
+
+        insert_before(
+            &mut blocks,
+            &[1, 1],
+            TokenBlock::with_annotation("@Deprecated".to_string()),
+        )
+        .unwrap();
+
+        assert_eq!(
+            render(&blocks),
+            "value = 5\n\n@Test first {\n    @Case one { 1 }\n    @Deprecated\n    @Case two { 2 }\n}\n\n@Test second { 5 }\n"
+        );
+        assert_eq!(blocks[1].blocks()[1].annotation_text(), "@Deprecated");
+        assert_eq!(
+            blocks,
+            collector()
+                .collect_tokens_from_input(&render(&blocks))
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn inserted_tokens_positioned() {
+        let mut blocks = collector().collect_tokens_from_input(INPUT).unwrap();
+        let inserted = collector()
+            .collect_tokens_from_input("@Case zero { 0 }")
+            .unwrap()
+            .remove(0);
+
+        insert_before(&mut blocks, &[1, 0], inserted).unwrap();
+        insert_before(
+            &mut blocks,
+            &[3],
+            TokenBlock::with_annotation("@Deprecated".to_string()),
+        )
+        .unwrap();
+
+        assert_eq!(
+            blocks,
+            collector()
+                .collect_tokens_from_input(&render(&blocks))
+                .unwrap()
+        );
+        assert_eq!(
+            blocks[1].blocks()[0].span(),
+            Some(Span::new((3, 4), (3, 20)))
+        );
+    }
+
+    fn modifier_collector() -> Collector {
+        let mut sinks = collector().into_sinks();
+        sinks.push(Sink::new("@Skip").modifier());
+        sinks.push(
+            Sink::new("@Tag")
+                .modifier()
+                .part(PartParser::new(PartBehavior::TokenCount(1))),
+        );
+        Collector::new(sinks)
+    }
+
+    const MODIFIED: &str = "x\n@Skip @Tag slow\n@Test name { 5 }\ny";
+
+    #[test]
+    fn remove_block_with_modifiers() {
+        let mut blocks = modifier_collector()
+            .collect_tokens_from_input(MODIFIED)
+            .unwrap();
+
+        remove_block(&mut blocks, &[1]).unwrap();
+
+        assert_eq!(render(&blocks), "x\n\ny");
+        assert_eq!(
+            blocks,
+            modifier_collector()
+                .collect_tokens_from_input(&render(&blocks))
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn insert_before_block_with_modifiers() {
+        let mut blocks = modifier_collector()
+            .collect_tokens_from_input(MODIFIED)
+            .unwrap();
+
+        insert_before(
+            &mut blocks,
+            &[1],
+            TokenBlock::with_annotation("@Deprecated".to_string()),
+        )
+        .unwrap();
+
+        assert_eq!(
+            render(&blocks),
+            "x\n@Deprecated\n@Skip @Tag slow\n@Test name { 5 }\ny"
+        );
+        assert_eq!(
+            blocks,
+            modifier_collector()
+                .collect_tokens_from_input(&render(&blocks))
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn invalid_path() {
+        let mut blocks = collector().collect_tokens_from_input(INPUT).unwrap();
+
+        assert_eq!(
+            remove_block(&mut blocks, &[1, 5]),
+            Err("No block at [1, 5]".to_string())
+        );
+    }
+}
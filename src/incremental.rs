@@ -0,0 +1,401 @@
+use garnish_lang_compiler::lex::{lex, LexerToken};
+
+use crate::collector::{Collector, TokenBlock};
+use crate::edit::merge_root_tokens;
+use crate::render::render;
+use crate::span::{position_after, Span};
+
+/// Replacement of the text between two zero based `(line, column)` positions, end exclusive.
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct TextEdit {
+    start: (usize, usize),
+    end: (usize, usize),
+    text: String,
+}
+
+impl TextEdit {
+    pub fn new<T: ToString>(start: (usize, usize), end: (usize, usize), text: T) -> Self {
+        Self {
+            start,
+            end,
+            text: text.to_string(),
+        }
+    }
+
+    pub fn insert<T: ToString>(at: (usize, usize), text: T) -> Self {
+        Self::new(at, at, text)
+    }
+
+    pub fn start(&self) -> (usize, usize) {
+        self.start
+    }
+
+    pub fn end(&self) -> (usize, usize) {
+        self.end
+    }
+
+    pub fn text(&self) -> &String {
+        &self.text
+    }
+
+    /// Applies the edit to text that starts at `origin`.
+    fn apply(&self, text: &str, origin: (usize, usize)) -> Result<String, String> {
+        let start = byte_offset(text, origin, self.start)?;
+        let end = byte_offset(text, origin, self.end)?;
+        if end < start {
+            return Err(format!(
+                "Edit end {:?} is before its start {:?}",
+                self.end, self.start
+            ));
+        }
+
+        Ok(format!("{}{}{}", &text[..start], self.text, &text[end..]))
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct Recollection {
+    blocks: Vec<TokenBlock>,
+    changed: Vec<usize>,
+}
+
+impl Recollection {
+    pub fn blocks(&self) -> &Vec<TokenBlock> {
+        &self.blocks
+    }
+
+    pub fn blocks_owned(self) -> Vec<TokenBlock> {
+        self.blocks
+    }
+
+    /// Indexes of top level blocks in [`Recollection::blocks`] that were collected again.
+    /// All other blocks are carried over from the previous result, moved to account for
+    /// lines and columns added or removed by the edit.
+    pub fn changed(&self) -> &Vec<usize> {
+        &self.changed
+    }
+}
+
+impl Collector {
+    /// Updates a previous collection result for an edit to its source text.
+    ///
+    /// Only the top level blocks touching the edit are rendered, edited, lexed and collected
+    /// again. When a re-collected block is left unfinished, following blocks are pulled in
+    /// until it ends, so the result matches collecting the whole edited text.
+    ///
+    /// Target and modifier blocks take in tokens from around their span, so collectors with
+    /// [`Sink::target`](crate::Sink::target) or [`Sink::modifier`](crate::Sink::modifier) sinks
+    /// collect all of the edited text again.
+    pub fn recollect(
+        &self,
+        previous: &[TokenBlock],
+        edit: &TextEdit,
+    ) -> Result<Recollection, String> {
+        let spans: Vec<Span> = previous
+            .iter()
+            .map(|block| block.span().unwrap_or_else(|| Span::new((0, 0), (0, 0))))
+            .collect();
+
+        if previous.is_empty() || self.reaches_past_blocks() {
+            let blocks = self.collect_tokens_from_input(&edit.apply(&render(previous), (0, 0))?)?;
+            return Ok(Recollection {
+                changed: (0..blocks.len()).collect(),
+                blocks,
+            });
+        }
+
+        let first = spans
+            .iter()
+            .position(|span| span.end() >= edit.start)
+            .unwrap_or(previous.len() - 1);
+        let mut last = spans
+            .iter()
+            .rposition(|span| span.start() <= edit.end)
+            .unwrap_or(first)
+            .max(first);
+
+        let origin = spans[first].start();
+        let (new_blocks, old_end, new_end) = loop {
+            let text = edit.apply(&render(&previous[first..=last]), origin)?;
+            let tokens: Vec<LexerToken> = lex(&text)?
+                .iter()
+                .map(|token| moved(token, (0, 0), origin))
+                .collect();
+
+            let (blocks, open) = self.collect_tokens_open(&tokens)?;
+            if open && last + 1 < previous.len() {
+                last += 1;
+                continue;
+            }
+
+            break (blocks, spans[last].end(), position_after(origin, &text));
+        };
+
+        let mut blocks = previous[..first].to_vec();
+        let mut changed_start = first;
+        blocks.extend(new_blocks);
+        let mut changed_end = blocks.len();
+        for block in previous[last + 1..].iter() {
+            let mut block = block.clone();
+            move_block(&mut block, old_end, new_end);
+            blocks.push(block);
+        }
+
+        // Root tokens on either side of the re-collected blocks may need joining
+        let count = blocks.len();
+        merge_root_tokens(&mut blocks, changed_end);
+        if changed_start == changed_end {
+            // Nothing re-collected, a join is the only change
+            return Ok(Recollection {
+                changed: match blocks.len() < count {
+                    true => vec![changed_start - 1],
+                    false => vec![],
+                },
+                blocks,
+            });
+        }
+
+        let count = blocks.len();
+        merge_root_tokens(&mut blocks, changed_start);
+        if blocks.len() < count {
+            changed_start -= 1;
+            changed_end -= 1;
+        }
+
+        Ok(Recollection {
+            changed: (changed_start..changed_end).collect(),
+            blocks,
+        })
+    }
+}
+
+fn byte_offset(
+    text: &str,
+    origin: (usize, usize),
+    position: (usize, usize),
+) -> Result<usize, String> {
+    let mut current = origin;
+    for (offset, c) in text.char_indices() {
+        if current == position {
+            return Ok(offset);
+        }
+        current = position_after(current, &c.to_string());
+    }
+
+    match current == position {
+        true => Ok(text.len()),
+        false => Err(format!(
+            "Position {:?} is outside of the document",
+            position
+        )),
+    }
+}
+
+// Moves a position at or after `from` so that `from` lands on `to`
+pub(crate) fn move_position(
+    position: (usize, usize),
+    from: (usize, usize),
+    to: (usize, usize),
+) -> (usize, usize) {
+    match position.0 == from.0 {
+        true => (to.0, to.1 + position.1 - from.1),
+        false => (position.0 - from.0 + to.0, position.1),
+    }
+}
+
+fn moved(token: &LexerToken, from: (usize, usize), to: (usize, usize)) -> LexerToken {
+    let (line, column) = move_position((token.get_line(), token.get_column()), from, to);
+    LexerToken::new(
+        token.get_text().clone(),
+        token.get_token_type(),
+        line,
+        column,
+    )
+}
+
+fn move_block(block: &mut TokenBlock, from: (usize, usize), to: (usize, usize)) {
+    if let Some(token) = block.annotation_token_mut() {
+        *token = moved(token, from, to);
+    }
+    for token in block.parts_mut().iter_mut().flatten() {
+        *token = moved(token, from, to);
+    }
+    for token in block.tokens_mut().iter_mut() {
+        *token = moved(token, from, to);
+    }
+    for modifier in block.modifiers_mut().iter_mut() {
+        move_block(modifier, from, to);
+    }
+    for child in block.blocks_mut().iter_mut() {
+        move_block(child, from, to);
+    }
+}
+
+#[cfg(test)]
+mod recollecting {
+    use garnish_lang_compiler::lex::TokenType;
+
+    use crate::{Collector, PartBehavior, PartParser, Sink, TextEdit};
+
+    fn collector() -> Collector {
+        Collector::new(vec![
+            Sink::new("@Test")
+                .part(PartParser::new(PartBehavior::TokenCount(1)))
+                .part(PartParser::new(PartBehavior::UntilToken(
+                    TokenType::EndExpression,
+                ))),
+            Sink::new("@Case")
+                .part(PartParser::new(PartBehavior::TokenCount(1)))
+                .part(PartParser::new(PartBehavior::UntilToken(
+                    TokenType::EndExpression,
+                ))),
+            Sink::new("@Def").part(PartParser::new(PartBehavior::UntilNewline)),
+        ])
+    }
+
+    const INPUT: &str = "value = 5\n\n@Test first {\n    @Case one { 1 }\n}\n\n@Def add\n@Test second { 5 }\nvalue + 1\n";
+
+    fn apply(input: &str, edit: &TextEdit) -> String {
+        edit.apply(input, (0, 0)).unwrap()
+    }
+
+    fn assert_matches_full_collection(edit: TextEdit) -> Vec<usize> {
+        assert_recollects(&collector(), INPUT, edit)
+    }
+
+    fn assert_recollects(collector: &Collector, input: &str, edit: TextEdit) -> Vec<usize> {
+        let previous = collector.collect_tokens_from_input(input).unwrap();
+
+        let result = collector.recollect(&previous, &edit).unwrap();
+
+        let expected = collector
+            .collect_tokens_from_input(&apply(input, &edit))
+            .unwrap();
+        assert_eq!(result.blocks(), &expected);
+        result.changed().clone()
+    }
+
+    #[test]
+    fn edit_within_part() {
+        let changed = assert_matches_full_collection(TextEdit::new((3, 10), (3, 13), "uno"));
+
+        assert_eq!(changed, vec![1]);
+    }
+
+    #[test]
+    fn edit_adding_lines_moves_following_blocks() {
+        let changed =
+            assert_matches_full_collection(TextEdit::insert((3, 19), "\n    @Case two { 2 }"));
+
+        assert_eq!(changed, vec![1]);
+    }
+
+    #[test]
+    fn edit_in_root_tokens() {
+        let changed = assert_matches_full_collection(TextEdit::new((0, 8), (0, 9), "10\n"));
+
+        assert_eq!(changed, vec![0]);
+    }
+
+    #[test]
+    fn unfinished_block_takes_following_blocks() {
+        assert_matches_full_collection(TextEdit::new((4, 0), (4, 1), ""));
+    }
+
+    #[test]
+    fn new_annotation_in_root_tokens() {
+        assert_matches_full_collection(TextEdit::insert((8, 0), "@Test third { 3 }\n"));
+    }
+
+    #[test]
+    fn remove_block() {
+        let changed = assert_matches_full_collection(TextEdit::new((6, 0), (7, 0), ""));
+
+        // Blocks touching either end of the edit are collected again
+        assert_eq!(changed, vec![2, 3]);
+    }
+
+    #[test]
+    fn append_to_end() {
+        assert_matches_full_collection(TextEdit::insert((9, 0), "@Def sub\n"));
+    }
+
+    #[test]
+    fn edit_in_target() {
+        let collector = Collector::new(vec![Sink::new("@Inline").target()]);
+
+        assert_recollects(
+            &collector,
+            "@Inline\nadd = 5\nz\n",
+            TextEdit::new((1, 6), (1, 7), "6"),
+        );
+    }
+
+    #[test]
+    fn edit_in_modifier() {
+        let collector = Collector::new(vec![
+            collector().sinks()[0].clone(),
+            Sink::new("@Skip").modifier(),
+            Sink::new("@Tag")
+                .modifier()
+                .part(PartParser::new(PartBehavior::TokenCount(1))),
+        ]);
+
+        assert_recollects(
+            &collector,
+            "@Skip @Tag slow @Test name { 1 }",
+            TextEdit::new((0, 11), (0, 15), "fast"),
+        );
+        assert_recollects(
+            &collector,
+            "@Skip @Tag slow @Test name { 1 }",
+            TextEdit::new((0, 16), (0, 21), "5"),
+        );
+    }
+
+    #[test]
+    fn edit_in_observed() {
+        let collector = Collector::new(vec![
+            collector().sinks()[0].clone(),
+            Sink::new("@Doc")
+                .observe()
+                .part(PartParser::new(PartBehavior::UntilNewline)),
+        ]);
+
+        assert_recollects(
+            &collector,
+            "@Doc adds\n@Test add { 1 }\n",
+            TextEdit::new((0, 5), (0, 9), "sums"),
+        );
+        assert_recollects(
+            &collector,
+            "5\n@Doc adds\n@Test add { 1 }\n",
+            TextEdit::new((2, 11), (2, 12), "2"),
+        );
+    }
+
+    #[test]
+    fn empty_previous() {
+        let collector = collector();
+
+        let result = collector
+            .recollect(&[], &TextEdit::insert((0, 0), INPUT))
+            .unwrap();
+
+        assert_eq!(
+            result.blocks(),
+            &collector.collect_tokens_from_input(INPUT).unwrap()
+        );
+    }
+
+    #[test]
+    fn position_outside_of_document() {
+        let collector = collector();
+        let previous = collector.collect_tokens_from_input(INPUT).unwrap();
+
+        assert_eq!(
+            collector.recollect(&previous, &TextEdit::insert((20, 0), "5")),
+            Err("Position (20, 0) is outside of the document".to_string())
+        );
+    }
+}
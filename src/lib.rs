@@ -1,3 +1,36 @@
+mod classify;
 mod collector;
+#[cfg(feature = "config")]
+mod config;
+mod declare;
+mod diagnostic;
+mod edit;
+mod incremental;
+#[cfg(feature = "lsp")]
+pub mod lsp;
+mod query;
+mod render;
+#[cfg(feature = "serde")]
+mod serde_support;
+mod span;
+mod spec;
+mod strip;
+pub mod testing;
+mod token_type;
+mod visit;
 
+pub use classify::*;
 pub use collector::*;
+#[cfg(feature = "config")]
+pub use config::*;
+pub use declare::*;
+pub use diagnostic::*;
+pub use edit::*;
+pub use incremental::*;
+pub use query::*;
+pub use render::*;
+pub use span::*;
+pub use spec::*;
+pub use strip::*;
+pub use token_type::*;
+pub use visit::*;
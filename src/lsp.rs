@@ -0,0 +1,305 @@
+// Editor features built from collection results, shaped after the Language Server Protocol.
+// Lines and characters are zero based, characters counted the same as lexer columns, which is
+// the protocol's utf-32 position encoding.
+
+use crate::collector::{Collector, TokenBlock};
+use crate::diagnostic::DiagnosticSeverity;
+use crate::span::Span;
+
+#[derive(Debug, Eq, PartialEq, Clone, Copy, Default)]
+pub struct Position {
+    pub line: usize,
+    pub character: usize,
+}
+
+impl Position {
+    pub fn new(line: usize, character: usize) -> Self {
+        Self { line, character }
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Clone, Copy, Default)]
+pub struct Range {
+    pub start: Position,
+    pub end: Position,
+}
+
+impl From<Span> for Range {
+    fn from(span: Span) -> Self {
+        Self {
+            start: Position::new(span.start().0, span.start().1),
+            end: Position::new(span.end().0, span.end().1),
+        }
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct DocumentSymbol {
+    /// Annotation text, e.g. `@Test`.
+    pub name: String,
+    /// Trimmed text of the first part, often the name given to the annotated item.
+    pub detail: Option<String>,
+    /// Whole block including its parts and nested blocks.
+    pub range: Range,
+    /// Just the annotation token.
+    pub selection_range: Range,
+    pub children: Vec<DocumentSymbol>,
+}
+
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub struct FoldingRange {
+    pub start_line: usize,
+    pub end_line: usize,
+}
+
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct Diagnostic {
+    pub range: Range,
+    pub severity: Severity,
+    pub message: String,
+}
+
+/// One symbol per annotated block, nested the same as [`TokenBlock::blocks`] with a block's
+/// modifiers as its first children. Blocks of root tokens are left out.
+pub fn document_symbols(blocks: &[TokenBlock]) -> Vec<DocumentSymbol> {
+    blocks
+        .iter()
+        .filter(|block| !block.annotation_text().is_empty())
+        .map(|block| {
+            let detail = block
+                .parts()
+                .first()
+                .map(|part| {
+                    part.iter()
+                        .map(|token| token.get_text().as_str())
+                        .collect::<String>()
+                        .trim()
+                        .to_string()
+                })
+                .filter(|detail| !detail.is_empty());
+
+            DocumentSymbol {
+                name: block.annotation_text().clone(),
+                detail,
+                range: block.span().map(Range::from).unwrap_or_default(),
+                selection_range: block
+                    .annotation_token()
+                    .map(|token| Range::from(Span::of_token(token)))
+                    .unwrap_or_default(),
+                children: document_symbols(block.modifiers())
+                    .into_iter()
+                    .chain(document_symbols(block.blocks()))
+                    .collect(),
+            }
+        })
+        .collect()
+}
+
+/// A range for every part spanning more than one line, outer blocks first.
+pub fn folding_ranges(blocks: &[TokenBlock]) -> Vec<FoldingRange> {
+    let mut ranges = vec![];
+    for block in blocks {
+        add_folding_ranges(block, &mut ranges);
+    }
+    ranges
+}
+
+fn add_folding_ranges(block: &TokenBlock, ranges: &mut Vec<FoldingRange>) {
+    for part in 0..block.parts().len() {
+        let span = match block.part_span(part) {
+            None => continue,
+            Some(span) => span,
+        };
+
+        // A part ending with a newline shouldn't fold the line after it
+        let end_line = match span.end() {
+            (line, 0) if line > 0 => line - 1,
+            (line, _) => line,
+        };
+        if end_line > span.start().0 {
+            ranges.push(FoldingRange {
+                start_line: span.start().0,
+                end_line,
+            });
+        }
+    }
+
+    for child in block.blocks() {
+        add_folding_ranges(child, ranges);
+    }
+}
+
+/// Every problem found collecting `input` with the sinks it declares added,
+/// see [`Collector::with_declarations`] and [`Collector::recover_input`].
+pub fn diagnostics(collector: &Collector, input: &str) -> Vec<Diagnostic> {
+    let diagnostics = match collector.with_declarations(input) {
+        Ok(collector) => collector.recover_input(input).1,
+        Err(diagnostic) => vec![diagnostic],
+    };
+    diagnostics
+        .iter()
+        .map(|diagnostic| {
+            let start = Position::new(diagnostic.line(), diagnostic.column());
+            Diagnostic {
+                range: Range {
+                    start,
+                    end: Position::new(start.line, start.character + 1),
+                },
+                severity: match diagnostic.severity() {
+                    DiagnosticSeverity::Error => Severity::Error,
+                    DiagnosticSeverity::Warning => Severity::Warning,
+                },
+                message: diagnostic.message().to_string(),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod language_server {
+    use garnish_lang_compiler::lex::TokenType;
+
+    use crate::lsp::{
+        diagnostics, document_symbols, folding_ranges, Diagnostic, DocumentSymbol, FoldingRange,
+        Position, Range, Severity,
+    };
+    use crate::{Collector, PartBehavior, PartParser, Sink};
+
+    fn collector() -> Collector {
+        Collector::new(vec![
+            Sink::new("@Test")
+                .part(PartParser::new(PartBehavior::TokenCount(1)))
+                .part(PartParser::new(PartBehavior::UntilToken(
+                    TokenType::EndExpression,
+                ))),
+            Sink::new("@Case")
+                .part(PartParser::new(PartBehavior::TokenCount(1)))
+                .part(PartParser::new(PartBehavior::UntilToken(
+                    TokenType::EndExpression,
+                ))),
+        ])
+    }
+
+    const INPUT: &str = "value = 5\n@Test first {\n    @Case one { 1 }\n}\n";
+
+    fn range(start: (usize, usize), end: (usize, usize)) -> Range {
+        Range {
+            start: Position::new(start.0, start.1),
+            end: Position::new(end.0, end.1),
+        }
+    }
+
+    #[test]
+    fn symbols() {
+        let blocks = collector().collect_tokens_from_input(INPUT).unwrap();
+
+        assert_eq!(
+            document_symbols(&blocks),
+            vec![DocumentSymbol {
+                name: "@Test".to_string(),
+                detail: Some("first".to_string()),
+                range: range((1, 0), (3, 1)),
+                selection_range: range((1, 0), (1, 5)),
+                children: vec![DocumentSymbol {
+                    name: "@Case".to_string(),
+                    detail: Some("one".to_string()),
+                    range: range((2, 4), (2, 19)),
+                    selection_range: range((2, 4), (2, 9)),
+                    children: vec![],
+                }],
+            }]
+        );
+    }
+
+    #[test]
+    fn folding() {
+        let blocks = collector().collect_tokens_from_input(INPUT).unwrap();
+
+        assert_eq!(
+            folding_ranges(&blocks),
+            vec![FoldingRange {
+                start_line: 1,
+                end_line: 3
+            }]
+        );
+    }
+
+    #[test]
+    fn diagnostic_for_unterminated_part() {
+        assert_eq!(
+            diagnostics(&collector(), "@Test first {\n 5\n"),
+            vec![Diagnostic {
+                range: range((0, 0), (0, 1)),
+                severity: Severity::Error,
+                message: "Part 2 of @Test is not terminated before the end of input".to_string(),
+            }]
+        );
+        assert!(diagnostics(&collector(), INPUT).is_empty());
+    }
+
+    #[test]
+    fn every_diagnostic() {
+        let found = diagnostics(&collector(), "@Other\n@Test first {\n 5\n");
+
+        assert_eq!(
+            found
+                .iter()
+                .map(|d| d.range.start)
+                .collect::<Vec<Position>>(),
+            vec![Position::new(0, 0), Position::new(1, 0)]
+        );
+        assert_eq!(
+            found.iter().map(|d| d.severity).collect::<Vec<Severity>>(),
+            vec![Severity::Warning, Severity::Error]
+        );
+    }
+
+    #[test]
+    fn declared_sinks_used() {
+        let input = "@annotation Skip modifier\n@Skip @Test first { 5 }\n";
+
+        assert!(diagnostics(&collector(), input).is_empty());
+        assert_eq!(
+            diagnostics(&collector(), "@annotation Test\n"),
+            vec![Diagnostic {
+                range: range((0, 0), (0, 1)),
+                severity: Severity::Error,
+                message: "@Test is already defined".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn modifier_symbols() {
+        let collector = Collector::new(vec![
+            Sink::new("@Test").part(PartParser::new(PartBehavior::UntilNewline)),
+            Sink::new("@Skip").modifier(),
+        ]);
+        let blocks = collector
+            .collect_tokens_from_input("@Skip @Test 5\n")
+            .unwrap();
+
+        assert_eq!(
+            document_symbols(&blocks),
+            vec![DocumentSymbol {
+                name: "@Test".to_string(),
+                detail: Some("5".to_string()),
+                range: range((0, 0), (1, 0)),
+                selection_range: range((0, 6), (0, 11)),
+                children: vec![DocumentSymbol {
+                    name: "@Skip".to_string(),
+                    detail: None,
+                    range: range((0, 0), (0, 5)),
+                    selection_range: range((0, 0), (0, 5)),
+                    children: vec![],
+                }],
+            }]
+        );
+    }
+}
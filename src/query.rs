@@ -0,0 +1,224 @@
+use garnish_lang_compiler::lex::LexerToken;
+
+use crate::collector::TokenBlock;
+
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct BlockMatch<'a> {
+    block: &'a TokenBlock,
+    ancestors: Vec<&'a TokenBlock>,
+}
+
+impl<'a> BlockMatch<'a> {
+    pub fn block(&self) -> &'a TokenBlock {
+        self.block
+    }
+
+    /// Ancestors of the matched block, outermost first.
+    pub fn ancestors(&self) -> &Vec<&'a TokenBlock> {
+        &self.ancestors
+    }
+
+    pub fn parent(&self) -> Option<&'a TokenBlock> {
+        self.ancestors.last().copied()
+    }
+}
+
+type PartsPredicate = Box<dyn Fn(&[Vec<LexerToken>]) -> bool>;
+
+/// Selects blocks by a path of annotations separated by `/`, e.g. `@Test/@Case`.
+/// Each segment must be the direct parent of the next, a `*` segment matches any annotation
+/// and the first segment may be at any depth. Modifiers are matched as siblings of the block
+/// they're grouped onto, ahead of it.
+pub struct Query {
+    path: Vec<String>,
+    predicates: Vec<PartsPredicate>,
+}
+
+impl Query {
+    pub fn path(path: &str) -> Self {
+        Self {
+            path: path
+                .split('/')
+                .map(|segment| segment.trim().to_string())
+                .filter(|segment| !segment.is_empty())
+                .collect(),
+            predicates: vec![],
+        }
+    }
+
+    pub fn annotation<T: ToString>(annotation: T) -> Self {
+        Self {
+            path: vec![annotation.to_string()],
+            predicates: vec![],
+        }
+    }
+
+    pub fn filter<F: Fn(&[Vec<LexerToken>]) -> bool + 'static>(mut self, predicate: F) -> Self {
+        self.predicates.push(Box::new(predicate));
+        self
+    }
+
+    pub fn run<'a>(&self, blocks: &'a [TokenBlock]) -> Vec<BlockMatch<'a>> {
+        let mut matches = vec![];
+        let mut ancestors = vec![];
+        for block in blocks {
+            self.collect_matches(block, &mut ancestors, &mut matches);
+        }
+        matches
+    }
+
+    fn collect_matches<'a>(
+        &self,
+        block: &'a TokenBlock,
+        ancestors: &mut Vec<&'a TokenBlock>,
+        matches: &mut Vec<BlockMatch<'a>>,
+    ) {
+        for modifier in block.modifiers() {
+            self.collect_matches(modifier, ancestors, matches);
+        }
+
+        if self.matches(block, ancestors) {
+            matches.push(BlockMatch {
+                block,
+                ancestors: ancestors.clone(),
+            });
+        }
+
+        ancestors.push(block);
+        for child in block.blocks() {
+            self.collect_matches(child, ancestors, matches);
+        }
+        ancestors.pop();
+    }
+
+    fn matches(&self, block: &TokenBlock, ancestors: &[&TokenBlock]) -> bool {
+        let (last, parents) = match self.path.split_last() {
+            None => return false,
+            Some(split) => split,
+        };
+
+        if !segment_matches(last, block) || parents.len() > ancestors.len() {
+            return false;
+        }
+
+        let chain = &ancestors[ancestors.len() - parents.len()..];
+        parents
+            .iter()
+            .zip(chain.iter())
+            .all(|(segment, ancestor)| segment_matches(segment, ancestor))
+            && self
+                .predicates
+                .iter()
+                .all(|predicate| predicate(block.parts()))
+    }
+}
+
+fn segment_matches(segment: &str, block: &TokenBlock) -> bool {
+    !block.annotation_text().is_empty() && (segment == "*" || block.annotation_text() == segment)
+}
+
+pub fn find_annotation<'a>(blocks: &'a [TokenBlock], annotation: &str) -> Vec<BlockMatch<'a>> {
+    Query::annotation(annotation).run(blocks)
+}
+
+pub fn select<'a>(blocks: &'a [TokenBlock], path: &str) -> Vec<BlockMatch<'a>> {
+    Query::path(path).run(blocks)
+}
+
+#[cfg(test)]
+mod querying {
+    use garnish_lang_compiler::lex::TokenType;
+
+    use crate::{find_annotation, select, Collector, PartBehavior, PartParser, Query, Sink};
+
+    fn collector() -> Collector {
+        Collector::new(vec![
+            Sink::new("@Suite").part(PartParser::new(PartBehavior::UntilAnnotation(
+                "EndSuite".to_string(),
+            ))),
+            Sink::new("@Test").part(PartParser::new(PartBehavior::UntilAnnotation(
+                "End".to_string(),
+            ))),
+            Sink::new("@Case").part(PartParser::new(PartBehavior::UntilNewline)),
+        ])
+    }
+
+    #[test]
+    fn finds_at_any_depth() {
+        let blocks = collector()
+            .collect_tokens_from_input("@Case 1\n@Suite\n@Test 5\n@Case 10\n@End\n@EndSuite")
+            .unwrap();
+
+        let matches = find_annotation(&blocks, "@Case");
+
+        assert_eq!(matches.len(), 2);
+        assert!(matches[0].ancestors().is_empty());
+        assert_eq!(
+            matches[1]
+                .ancestors()
+                .iter()
+                .map(|block| block.annotation_text().as_str())
+                .collect::<Vec<&str>>(),
+            vec!["@Suite", "@Test"]
+        );
+        assert_eq!(matches[1].parent().unwrap().annotation_text(), "@Test");
+    }
+
+    #[test]
+    fn path_requires_direct_parent() {
+        let blocks = collector()
+            .collect_tokens_from_input(
+                "@Case 1\n@Suite\n@Case 2\n@Test 5\n@Case 10\n@End\n@EndSuite",
+            )
+            .unwrap();
+
+        assert_eq!(select(&blocks, "@Test/@Case").len(), 1);
+        assert_eq!(select(&blocks, "@Suite/@Case").len(), 1);
+        assert_eq!(select(&blocks, "@Suite/*/@Case").len(), 1);
+        assert_eq!(select(&blocks, "@Suite/@Test/@Case").len(), 1);
+        assert_eq!(select(&blocks, "@Test/@Suite").len(), 0);
+    }
+
+    #[test]
+    fn filter_on_parts() {
+        let blocks = collector()
+            .collect_tokens_from_input("@Test 5\n@Case 10\n@Case abc\n@End")
+            .unwrap();
+
+        let matches = Query::path("@Test/@Case")
+            .filter(|parts| {
+                parts[0]
+                    .iter()
+                    .any(|token| token.get_token_type() == TokenType::Identifier)
+            })
+            .run(&blocks);
+
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].block().parts()[0][1].get_text(), "abc");
+    }
+
+    #[test]
+    fn finds_modifiers() {
+        let collector = Collector::new(vec![
+            Sink::new("@Test").part(PartParser::new(PartBehavior::UntilAnnotation(
+                "End".to_string(),
+            ))),
+            Sink::new("@Case").part(PartParser::new(PartBehavior::UntilNewline)),
+            Sink::new("@Skip").modifier(),
+        ]);
+        let blocks = collector
+            .collect_tokens_from_input(
+                "@Skip @Test 5
+@Skip @Case 10
+@End",
+            )
+            .unwrap();
+
+        let matches = find_annotation(&blocks, "@Skip");
+
+        assert_eq!(matches.len(), 2);
+        assert!(matches[0].ancestors().is_empty());
+        assert_eq!(matches[1].parent().unwrap().annotation_text(), "@Test");
+        assert_eq!(select(&blocks, "@Test/@Skip").len(), 1);
+    }
+}
@@ -0,0 +1,216 @@
+use garnish_lang_compiler::lex::LexerToken;
+
+use crate::collector::{PartEntry, TokenBlock};
+
+/// Reproduces the source text a list of blocks was collected from, placing nested blocks
+/// where they occurred within their parent's parts.
+pub fn render(blocks: &[TokenBlock]) -> String {
+    let mut output = String::new();
+    for item in in_source_order(blocks) {
+        match item {
+            Item::Block(block) => render_block(block, &mut output),
+            Item::Token(token) => output.push_str(token.get_text()),
+        }
+    }
+    output
+}
+
+enum Item<'a> {
+    Block(&'a TokenBlock),
+    Token(&'a LexerToken),
+}
+
+impl Item<'_> {
+    fn start(&self) -> Option<(usize, usize)> {
+        match self {
+            Item::Block(block) => block.annotation_token(),
+            Item::Token(token) => Some(*token),
+        }
+        .map(|token| (token.get_line(), token.get_column()))
+    }
+}
+
+// Blocks with their modifiers ahead of them. Whitespace between modifiers is left in the root
+// block ahead of the block they're grouped onto, so order root tokens and blocks by where each
+// starts when that's known.
+fn in_source_order(blocks: &[TokenBlock]) -> Vec<Item<'_>> {
+    if blocks.iter().all(|block| block.modifiers().is_empty()) {
+        return blocks.iter().map(Item::Block).collect();
+    }
+
+    let mut ordered: Vec<Item> = blocks
+        .iter()
+        .flat_map(|block| -> Vec<Item> {
+            match block.annotation_text().is_empty() {
+                true => block.tokens().iter().map(Item::Token).collect(),
+                false => block
+                    .modifiers()
+                    .iter()
+                    .chain(std::iter::once(block))
+                    .map(Item::Block)
+                    .collect(),
+            }
+        })
+        .collect();
+
+    if ordered.iter().all(|item| item.start().is_some()) {
+        ordered.sort_by_key(Item::start);
+    }
+    ordered
+}
+
+fn render_block(block: &TokenBlock, output: &mut String) {
+    // Observed tokens are rendered where they were left
+    if block.observed() {
+        return;
+    }
+
+    match block.annotation_token() {
+        Some(token) => output.push_str(token.get_text()),
+        None => output.push_str(block.annotation_text()),
+    }
+
+    for part in 0..block.parts().len() {
+        for entry in block.part_entries(part) {
+            match entry {
+                PartEntry::Token(token) => output.push_str(token.get_text()),
+                PartEntry::NestedBlock(index) => render_block(&block.blocks()[index], output),
+                PartEntry::Modifier(index, modifier) => {
+                    render_block(&block.blocks()[index].modifiers()[modifier], output)
+                }
+            }
+        }
+    }
+
+    // Nested blocks not placed within a part follow the parts
+    for child in block.blocks().iter().filter(|child| {
+        child
+            .nesting()
+            .map(|position| position.part() >= block.parts().len())
+            .unwrap_or(true)
+    }) {
+        for modifier in child.modifiers() {
+            render_block(modifier, output);
+        }
+        render_block(child, output);
+    }
+
+    for token in block.tokens() {
+        output.push_str(token.get_text());
+    }
+}
+
+#[cfg(test)]
+mod rendering {
+    use garnish_lang_compiler::lex::TokenType;
+
+    use garnish_lang_compiler::lex::LexerToken;
+
+    use crate::{render, Collector, PartBehavior, PartParser, Sink, TokenBlock};
+
+    fn test_sink(behavior: PartBehavior) -> Sink {
+        Sink::new("@Test").part(PartParser::new(behavior))
+    }
+
+    fn fixtures() -> Vec<(Collector, &'static str)> {
+        vec![
+            (Collector::new(vec![Sink::new("@Test")]), "@Test 5"),
+            (
+                Collector::new(vec![Sink::new("@Test")
+                    .part(PartParser::new(PartBehavior::TokenCount(1)))
+                    .part(PartParser::new(PartBehavior::UntilNewline))]),
+                "@Test name 5",
+            ),
+            (
+                Collector::new(vec![test_sink(PartBehavior::UntilNewline)]),
+                "@Test 5 + 5   \n   5 + 5",
+            ),
+            (
+                Collector::new(vec![test_sink(PartBehavior::TokenCount(5))]),
+                "@Test 5 + 5 + 5 + 5 + 5",
+            ),
+            (
+                Collector::new(vec![test_sink(PartBehavior::UntilToken(
+                    TokenType::EndExpression,
+                ))]),
+                "@Test { 5 + 5 } 5 + 5",
+            ),
+            (
+                Collector::new(vec![test_sink(PartBehavior::UntilToken(
+                    TokenType::EndExpression,
+                ))]),
+                "@Test { 5 + 5 }",
+            ),
+            (
+                Collector::new(vec![test_sink(PartBehavior::UntilToken(
+                    TokenType::EndExpression,
+                ))]),
+                "@Test {5,{5+5},5}",
+            ),
+            (
+                Collector::new(vec![test_sink(PartBehavior::UntilAnnotation(
+                    "End".to_string(),
+                ))]),
+                "@Test 5 + 5 @End 5 + 5",
+            ),
+            (
+                Collector::new(vec![
+                    test_sink(PartBehavior::UntilAnnotation("End".to_string())),
+                    Sink::new("@Case").part(PartParser::new(PartBehavior::UntilNewline)),
+                ]),
+                "@Test 5+5\n@Case 10+10\n@Case 20+20\n@End",
+            ),
+            (
+                Collector::new(vec![
+                    test_sink(PartBehavior::UntilToken(TokenType::EndExpression)),
+                    Sink::new("@Case").part(PartParser::new(PartBehavior::UntilToken(
+                        TokenType::EndExpression,
+                    ))),
+                ]),
+                "@Test { 5+5\n@Case { 10+10 }\n }",
+            ),
+        ]
+    }
+
+    #[test]
+    fn round_trip_fixtures() {
+        for (collector, input) in fixtures() {
+            let blocks = collector.collect_tokens_from_input(input).unwrap();
+
+            assert_eq!(render(&blocks), input);
+        }
+    }
+
+    #[test]
+    fn keeps_annotations_without_sinks() {
+        let input = "@Other 5\n@Test 10\n@Other";
+        let collector = Collector::new(vec![test_sink(PartBehavior::UntilNewline)]);
+
+        let blocks = collector.collect_tokens_from_input(input).unwrap();
+
+        assert_eq!(render(&blocks), input);
+    }
+
+    #[test]
+    fn blocks_built_by_hand() {
+        let blocks = vec![
+            TokenBlock::new_with_parts(
+                "@Test".to_string(),
+                vec![],
+                vec![vec![
+                    LexerToken::new(" ".to_string(), TokenType::Whitespace, 0, 0),
+                    LexerToken::new("5".to_string(), TokenType::Number, 0, 0),
+                ]],
+            )
+            .and_children(vec![TokenBlock::with_annotation(" @Skip".to_string())]),
+            TokenBlock::with_tokens(vec![LexerToken::new(
+                "\n".to_string(),
+                TokenType::Whitespace,
+                0,
+                0,
+            )]),
+        ];
+
+        assert_eq!(render(&blocks), "@Test 5 @Skip\n");
+    }
+}
@@ -0,0 +1,246 @@
+use garnish_lang_compiler::lex::{LexerToken, TokenType};
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::spec::annotation_name;
+use crate::token_type::{token_type_from_name, token_type_name};
+
+#[derive(Serialize, Deserialize)]
+struct Token {
+    text: String,
+    token_type: String,
+    line: usize,
+    column: usize,
+}
+
+impl Token {
+    fn from_lexer_token(token: &LexerToken) -> Self {
+        Self {
+            text: token.get_text().clone(),
+            token_type: token_type_name(token.get_token_type()),
+            line: token.get_line(),
+            column: token.get_column(),
+        }
+    }
+
+    fn into_lexer_token<E: Error>(self) -> Result<LexerToken, E> {
+        Ok(LexerToken::new(
+            self.text,
+            parse_token_type(&self.token_type)?,
+            self.line,
+            self.column,
+        ))
+    }
+}
+
+fn parse_token_type<E: Error>(name: &str) -> Result<TokenType, E> {
+    token_type_from_name(name).ok_or_else(|| E::custom(format!("Unknown token type '{}'", name)))
+}
+
+pub(crate) mod token_type {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(
+        token_type: &TokenType,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&token_type_name(*token_type))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<TokenType, D::Error> {
+        parse_token_type(&String::deserialize(deserializer)?)
+    }
+}
+
+pub(crate) mod token_types {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(
+        token_types: &[TokenType],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(token_types.iter().map(|t| token_type_name(*t)))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<TokenType>, D::Error> {
+        Vec::<String>::deserialize(deserializer)?
+            .iter()
+            .map(|name| parse_token_type(name))
+            .collect()
+    }
+}
+
+pub(crate) mod optional_token {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(
+        token: &Option<LexerToken>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        token
+            .as_ref()
+            .map(Token::from_lexer_token)
+            .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<LexerToken>, D::Error> {
+        Option::<Token>::deserialize(deserializer)?
+            .map(Token::into_lexer_token)
+            .transpose()
+    }
+}
+
+pub(crate) mod tokens {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(
+        tokens: &[LexerToken],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(tokens.iter().map(Token::from_lexer_token))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<LexerToken>, D::Error> {
+        Vec::<Token>::deserialize(deserializer)?
+            .into_iter()
+            .map(Token::into_lexer_token)
+            .collect()
+    }
+}
+
+// Names in allowed_parents and allowed_children get a leading @, as in sink specs.
+pub(crate) mod annotation_names {
+    use super::*;
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Vec<String>>, D::Error> {
+        Ok(Option::<Vec<String>>::deserialize(deserializer)?
+            .map(|names| names.iter().map(|name| annotation_name(name)).collect()))
+    }
+}
+
+pub(crate) mod parts {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(
+        parts: &[Vec<LexerToken>],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(parts.iter().map(|part| {
+            part.iter()
+                .map(Token::from_lexer_token)
+                .collect::<Vec<Token>>()
+        }))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<Vec<LexerToken>>, D::Error> {
+        Vec::<Vec<Token>>::deserialize(deserializer)?
+            .into_iter()
+            .map(|part| part.into_iter().map(Token::into_lexer_token).collect())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod serializing {
+    use garnish_lang_compiler::lex::TokenType;
+
+    use crate::{Collector, PartBehavior, PartParser, Sink, TokenBlock};
+
+    fn collector() -> Collector {
+        Collector::new(vec![
+            Sink::new("@Test").part(PartParser::new(PartBehavior::UntilAnnotation(
+                "End".to_string(),
+            ))),
+            Sink::new("@Case").part(PartParser::new(PartBehavior::UntilToken(
+                TokenType::EndExpression,
+            ))),
+        ])
+    }
+
+    #[test]
+    fn token_fields() {
+        let blocks = collector()
+            .collect_tokens_from_input("@Test 5 @End")
+            .unwrap();
+
+        let value = serde_json::to_value(&blocks).unwrap();
+
+        assert_eq!(value[0]["annotation_text"], "@Test");
+        assert_eq!(
+            value[0]["annotation"],
+            serde_json::json!({"text": "@Test", "token_type": "Annotation", "line": 0, "column": 0})
+        );
+        assert_eq!(
+            value[0]["parts"][0][1],
+            serde_json::json!({"text": "5", "token_type": "Number", "line": 0, "column": 6})
+        );
+    }
+
+    #[test]
+    fn blocks_round_trip() {
+        let blocks = collector()
+            .collect_tokens_from_input("@Test 5\n@Case { 10 }\n@End 5 + 5")
+            .unwrap();
+
+        let json = serde_json::to_string(&blocks).unwrap();
+        let restored: Vec<TokenBlock> = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(restored, blocks);
+    }
+
+    #[test]
+    fn parsed_blocks_round_trip() {
+        let collector = Collector::new(vec![
+            Sink::new("@Skip").modifier(),
+            Sink::new("@Expect")
+                .part(PartParser::new(PartBehavior::UntilNewline).parsed())
+                .part(PartParser::new(PartBehavior::TokenCount(1))),
+        ]);
+        let blocks = collector
+            .collect_tokens_from_input(
+                "@Expect 5 + 5
+10
+@Skip @Expect 1
+1",
+            )
+            .unwrap();
+
+        let json = serde_json::to_string(&blocks).unwrap();
+        let mut restored: Vec<TokenBlock> = serde_json::from_str(&json).unwrap();
+
+        // Parse results are left out and restored from the collector
+        assert!(blocks[0].parsed_part(0).is_some());
+        assert_eq!(restored[0].parsed_part(0), None);
+        collector.parse_blocks(&mut restored);
+        assert_eq!(restored, blocks);
+    }
+
+    #[test]
+    fn collector_round_trip() {
+        let collector = collector();
+
+        let json = serde_json::to_string(&collector).unwrap();
+        let restored: Collector = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(restored, collector);
+    }
+
+    #[test]
+    fn unknown_token_type() {
+        let json = r#"{"annotation_text":"","nested":[],"tokens":[{"text":"5","token_type":"Nope","line":0,"column":0}],"parts":[]}"#;
+
+        let error = serde_json::from_str::<TokenBlock>(json).unwrap_err();
+
+        assert!(error.to_string().contains("Unknown token type 'Nope'"));
+    }
+}
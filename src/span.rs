@@ -0,0 +1,218 @@
+use garnish_lang_compiler::lex::LexerToken;
+
+use crate::collector::TokenBlock;
+use crate::visit::iter_depth_first;
+
+/// Range of source text as zero based `(line, column)` pairs, end exclusive.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub struct Span {
+    start: (usize, usize),
+    end: (usize, usize),
+}
+
+impl Span {
+    pub fn new(start: (usize, usize), end: (usize, usize)) -> Self {
+        Self { start, end }
+    }
+
+    pub fn of_token(token: &LexerToken) -> Self {
+        let start = (token.get_line(), token.get_column());
+        Self::new(start, position_after(start, token.get_text()))
+    }
+
+    pub fn of_tokens<'a, T: IntoIterator<Item = &'a LexerToken>>(tokens: T) -> Option<Self> {
+        tokens
+            .into_iter()
+            .map(Span::of_token)
+            .reduce(|span, next| span.merge(&next))
+    }
+
+    pub fn start(&self) -> (usize, usize) {
+        self.start
+    }
+
+    pub fn end(&self) -> (usize, usize) {
+        self.end
+    }
+
+    pub fn contains(&self, line: usize, column: usize) -> bool {
+        self.start <= (line, column) && (line, column) < self.end
+    }
+
+    pub fn merge(&self, other: &Span) -> Span {
+        Span::new(self.start.min(other.start), self.end.max(other.end))
+    }
+}
+
+/// Position reached after reading `text` starting at `start`.
+pub fn position_after(start: (usize, usize), text: &str) -> (usize, usize) {
+    text.chars().fold(start, |(line, column), c| match c {
+        '\n' => (line + 1, 0),
+        _ => (line, column + 1),
+    })
+}
+
+impl TokenBlock {
+    /// Source covered by this block's annotation, parts, nested blocks and tokens.
+    /// Blocks built without positioned tokens have no span.
+    pub fn span(&self) -> Option<Span> {
+        Span::of_tokens(
+            iter_depth_first(std::slice::from_ref(self)).flat_map(|(_, block)| {
+                block
+                    .annotation_token()
+                    .into_iter()
+                    .chain(block.parts().iter().flatten())
+                    .chain(block.tokens().iter())
+            }),
+        )
+    }
+
+    /// Span of a single part's tokens, not including blocks nested in it.
+    pub fn part_span(&self, part: usize) -> Option<Span> {
+        Span::of_tokens(self.parts().get(part)?.iter())
+    }
+}
+
+/// Innermost annotated block covering a position, see [`find_block_at`].
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct BlockAt<'a> {
+    block: &'a TokenBlock,
+    part: Option<usize>,
+    ancestors: Vec<&'a TokenBlock>,
+}
+
+impl<'a> BlockAt<'a> {
+    pub fn block(&self) -> &'a TokenBlock {
+        self.block
+    }
+
+    /// Part containing the position, `None` when it is on the annotation itself.
+    pub fn part(&self) -> Option<usize> {
+        self.part
+    }
+
+    /// Ancestors of the found block, outermost first.
+    pub fn ancestors(&self) -> &Vec<&'a TokenBlock> {
+        &self.ancestors
+    }
+}
+
+/// Finds the innermost annotated block whose span contains the zero based `line` and `column`.
+/// Positions within root tokens find nothing.
+pub fn find_block_at(blocks: &[TokenBlock], line: usize, column: usize) -> Option<BlockAt<'_>> {
+    let mut ancestors = vec![];
+    let mut block = containing(blocks, line, column)?;
+    while let Some(child) = containing(block.blocks(), line, column) {
+        ancestors.push(block);
+        block = child;
+    }
+
+    let part = (0..block.parts().len()).find(|part| {
+        block
+            .part_span(*part)
+            .map(|span| span.contains(line, column))
+            .unwrap_or(false)
+    });
+
+    Some(BlockAt {
+        block,
+        part,
+        ancestors,
+    })
+}
+
+fn containing(blocks: &[TokenBlock], line: usize, column: usize) -> Option<&TokenBlock> {
+    blocks.iter().find(|block| {
+        !block.annotation_text().is_empty()
+            && block
+                .span()
+                .map(|span| span.contains(line, column))
+                .unwrap_or(false)
+    })
+}
+
+#[cfg(test)]
+mod spans {
+    use garnish_lang_compiler::lex::TokenType;
+
+    use crate::{find_block_at, Collector, PartBehavior, PartParser, Sink, Span, TokenBlock};
+
+    fn collect(input: &str) -> Vec<TokenBlock> {
+        Collector::new(vec![
+            Sink::new("@Test").part(PartParser::new(PartBehavior::UntilAnnotation(
+                "End".to_string(),
+            ))),
+            Sink::new("@Case").part(PartParser::new(PartBehavior::UntilToken(
+                TokenType::EndExpression,
+            ))),
+        ])
+        .collect_tokens_from_input(input)
+        .unwrap()
+    }
+
+    const INPUT: &str = "5\n@Test 5\n  @Case { 10 }\n@End\n";
+
+    #[test]
+    fn block_spans() {
+        let blocks = collect(INPUT);
+
+        assert_eq!(blocks[0].span(), Some(Span::new((0, 0), (1, 0))));
+        assert_eq!(blocks[1].span(), Some(Span::new((1, 0), (3, 4))));
+        assert_eq!(
+            blocks[1].blocks()[0].span(),
+            Some(Span::new((2, 2), (2, 14)))
+        );
+        assert_eq!(blocks[1].part_span(0), Some(Span::new((1, 5), (3, 4))));
+        assert_eq!(blocks[2].span(), Some(Span::new((3, 4), (4, 0))));
+    }
+
+    #[test]
+    fn contains() {
+        let span = Span::new((1, 4), (3, 2));
+
+        assert!(span.contains(1, 4));
+        assert!(span.contains(2, 100));
+        assert!(!span.contains(3, 2));
+        assert!(!span.contains(1, 3));
+    }
+
+    #[test]
+    fn innermost_block_at_position() {
+        let blocks = collect(INPUT);
+
+        let found = find_block_at(&blocks, 2, 10).unwrap();
+
+        assert_eq!(found.block(), &blocks[1].blocks()[0]);
+        assert_eq!(found.part(), Some(0));
+        assert_eq!(found.ancestors(), &vec![&blocks[1]]);
+    }
+
+    #[test]
+    fn position_in_parent_part() {
+        let blocks = collect(INPUT);
+
+        let found = find_block_at(&blocks, 1, 6).unwrap();
+
+        assert_eq!(found.block(), &blocks[1]);
+        assert_eq!(found.part(), Some(0));
+        assert!(found.ancestors().is_empty());
+    }
+
+    #[test]
+    fn position_on_annotation() {
+        let blocks = collect(INPUT);
+
+        let found = find_block_at(&blocks, 2, 3).unwrap();
+
+        assert_eq!(found.block().annotation_text(), "@Case");
+        assert_eq!(found.part(), None);
+    }
+
+    #[test]
+    fn position_in_root_tokens() {
+        let blocks = collect(INPUT);
+
+        assert_eq!(find_block_at(&blocks, 0, 0), None);
+        assert_eq!(find_block_at(&blocks, 10, 0), None);
+    }
+}
@@ -0,0 +1,225 @@
+use garnish_lang_compiler::lex::TokenType;
+
+use crate::collector::{PartBehavior, PartParser, Sink};
+use crate::token_type::token_type_from_name;
+
+/// Parses a single line sink definition of the form `@Annotation=part,part,...`.
+///
+/// Parts are written as `newline`, `expression`, `count:N`, `token:TokenType`, `annotation:Name`
+/// or `start-end:TokenType:TokenType`. A definition without `=` creates a sink with no parts.
+/// Listing `target` makes the sink capture a target, see [`Sink::target`], `modifier` groups
+/// its blocks onto the following block, see [`Sink::modifier`], and `observe` leaves its tokens
+/// in place, see [`Sink::observe`]. Nesting is limited with `top-level`, `parent:Name` and
+/// `child:Name`, the latter two repeated for each allowed annotation.
+pub fn parse_sink_spec(spec: &str) -> Result<Sink, String> {
+    let (annotation, parts) = match spec.split_once('=') {
+        None => (spec.trim(), ""),
+        Some((annotation, parts)) => (annotation.trim(), parts.trim()),
+    };
+
+    if !annotation.starts_with('@') || annotation.len() < 2 {
+        return Err(format!(
+            "Invalid annotation '{}' in sink definition '{}'",
+            annotation, spec
+        ));
+    }
+
+    let mut sink = Sink::new(annotation);
+    let mut parents = vec![];
+    let mut children = vec![];
+    for part in parts.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        sink = match part.split_once(':') {
+            Some(("parent", name)) => {
+                parents.push(annotation_name(name));
+                sink
+            }
+            Some(("child", name)) => {
+                children.push(annotation_name(name));
+                sink
+            }
+            _ => match part {
+                "target" => sink.target(),
+                "modifier" => sink.modifier(),
+                "observe" => sink.observe(),
+                "top-level" => sink.top_level_only(),
+                part => sink.part(PartParser::new(parse_behavior(part)?)),
+            },
+        };
+    }
+
+    if !parents.is_empty() {
+        sink = sink.allowed_parents(parents);
+    }
+    if !children.is_empty() {
+        sink = sink.allowed_children(children);
+    }
+
+    Ok(sink)
+}
+
+pub(crate) fn annotation_name(name: &str) -> String {
+    format!("@{}", name.trim().trim_start_matches('@'))
+}
+
+/// Parses sink definitions, one per line. Blank lines and lines starting with `#` are skipped.
+pub fn parse_sink_specs(specs: &str) -> Result<Vec<Sink>, String> {
+    specs
+        .lines()
+        .enumerate()
+        .map(|(number, line)| (number, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(number, line)| {
+            parse_sink_spec(line).map_err(|err| format!("Line {}: {}", number + 1, err))
+        })
+        .collect()
+}
+
+pub(crate) fn parse_behavior(part: &str) -> Result<PartBehavior, String> {
+    let mut pieces = part.split(':');
+    let behavior = match (pieces.next(), pieces.next(), pieces.next()) {
+        (Some("newline"), None, None) => PartBehavior::UntilNewline,
+        (Some("expression"), None, None) => PartBehavior::Expression,
+        (Some("count"), Some(count), None) => PartBehavior::TokenCount(
+            count
+                .parse()
+                .map_err(|_| format!("Invalid token count '{}'", count))?,
+        ),
+        (Some("token"), Some(token_type), None) => {
+            PartBehavior::UntilToken(parse_token_type(token_type)?)
+        }
+        (Some("annotation"), Some(annotation), None) => {
+            PartBehavior::UntilAnnotation(annotation.trim_start_matches('@').to_string())
+        }
+        (Some("start-end"), Some(start), Some(end)) => PartBehavior::StartEnd {
+            start: parse_token_type(start)?,
+            end: parse_token_type(end)?,
+        },
+        _ => return Err(format!("Invalid part definition '{}'", part)),
+    };
+
+    match pieces.next() {
+        None => Ok(behavior),
+        Some(_) => Err(format!("Invalid part definition '{}'", part)),
+    }
+}
+
+fn parse_token_type(name: &str) -> Result<TokenType, String> {
+    token_type_from_name(name).ok_or_else(|| format!("Unknown token type '{}'", name))
+}
+
+#[cfg(test)]
+mod spec_parsing {
+    use garnish_lang_compiler::lex::TokenType;
+
+    use crate::{parse_sink_spec, parse_sink_specs, PartBehavior, PartParser, Sink};
+
+    #[test]
+    fn annotation_only() {
+        assert_eq!(parse_sink_spec("@Skip").unwrap(), Sink::new("@Skip"));
+    }
+
+    #[test]
+    fn target() {
+        assert_eq!(
+            parse_sink_spec("@Inline=target").unwrap(),
+            Sink::new("@Inline").target()
+        );
+    }
+
+    #[test]
+    fn flags() {
+        assert_eq!(
+            parse_sink_spec("@Tag=modifier,count:1").unwrap(),
+            Sink::new("@Tag")
+                .modifier()
+                .part(PartParser::new(PartBehavior::TokenCount(1)))
+        );
+        assert_eq!(
+            parse_sink_spec("@Doc=observe,newline").unwrap(),
+            Sink::new("@Doc")
+                .observe()
+                .part(PartParser::new(PartBehavior::UntilNewline))
+        );
+    }
+
+    #[test]
+    fn nesting_rules() {
+        assert_eq!(
+            parse_sink_spec("@Case=parent:@Test,parent:Suite,count:1").unwrap(),
+            Sink::new("@Case")
+                .part(PartParser::new(PartBehavior::TokenCount(1)))
+                .allowed_parents(["@Test", "@Suite"])
+        );
+        assert_eq!(
+            parse_sink_spec("@Test=top-level,child:Case").unwrap(),
+            Sink::new("@Test")
+                .top_level_only()
+                .allowed_children(["@Case"])
+        );
+    }
+
+    #[test]
+    fn all_behaviors() {
+        assert_eq!(
+            parse_sink_spec(
+                "@Test = count:1, newline, token:EndExpression, annotation:@End, start-end:StartGroup:EndGroup, expression"
+            )
+            .unwrap(),
+            Sink::new("@Test")
+                .part(PartParser::new(PartBehavior::TokenCount(1)))
+                .part(PartParser::new(PartBehavior::UntilNewline))
+                .part(PartParser::new(PartBehavior::UntilToken(
+                    TokenType::EndExpression
+                )))
+                .part(PartParser::new(PartBehavior::UntilAnnotation(
+                    "End".to_string()
+                )))
+                .part(PartParser::new(PartBehavior::StartEnd {
+                    start: TokenType::StartGroup,
+                    end: TokenType::EndGroup
+                }))
+                .part(PartParser::new(PartBehavior::Expression))
+        );
+    }
+
+    #[test]
+    fn unknown_token_type() {
+        assert_eq!(
+            parse_sink_spec("@Test=token:Brace"),
+            Err("Unknown token type 'Brace'".to_string())
+        );
+    }
+
+    #[test]
+    fn invalid_part() {
+        assert_eq!(
+            parse_sink_spec("@Test=count:many"),
+            Err("Invalid token count 'many'".to_string())
+        );
+        assert_eq!(
+            parse_sink_spec("@Test=newline:1"),
+            Err("Invalid part definition 'newline:1'".to_string())
+        );
+    }
+
+    #[test]
+    fn multiple_lines() {
+        let sinks = parse_sink_specs("# test sinks\n@Test=newline\n\n@Case=count:1\n").unwrap();
+
+        assert_eq!(
+            sinks,
+            vec![
+                Sink::new("@Test").part(PartParser::new(PartBehavior::UntilNewline)),
+                Sink::new("@Case").part(PartParser::new(PartBehavior::TokenCount(1))),
+            ]
+        );
+    }
+
+    #[test]
+    fn error_reports_line() {
+        assert_eq!(
+            parse_sink_specs("@Test=newline\nTest=newline"),
+            Err("Line 2: Invalid annotation 'Test' in sink definition 'Test=newline'".to_string())
+        );
+    }
+}
@@ -0,0 +1,279 @@
+use std::collections::HashMap;
+
+use garnish_lang_compiler::lex::{lex, LexerToken, TokenType};
+
+use crate::collector::{Collector, PartBehavior, TokenBlock};
+use crate::visit::iter_depth_first;
+
+/// Input tokens left after removing annotations, with a map back to the original source.
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct StrippedTokens {
+    tokens: Vec<LexerToken>,
+    origins: Vec<usize>,
+    text_positions: Vec<(usize, usize)>,
+}
+
+impl StrippedTokens {
+    fn new(tokens: Vec<LexerToken>, origins: Vec<usize>) -> Self {
+        let mut text_positions = vec![];
+        let (mut line, mut column) = (0, 0);
+        for token in tokens.iter() {
+            text_positions.push((line, column));
+            for c in token.get_text().chars() {
+                match c {
+                    '\n' => {
+                        line += 1;
+                        column = 0;
+                    }
+                    _ => column += 1,
+                }
+            }
+        }
+
+        Self {
+            tokens,
+            origins,
+            text_positions,
+        }
+    }
+
+    /// Remaining tokens, still carrying the line and column they had in the original input.
+    pub fn tokens(&self) -> &Vec<LexerToken> {
+        &self.tokens
+    }
+
+    /// Index into the original token list for each remaining token.
+    pub fn origins(&self) -> &Vec<usize> {
+        &self.origins
+    }
+
+    pub fn text(&self) -> String {
+        self.tokens
+            .iter()
+            .map(|token| token.get_text().as_str())
+            .collect()
+    }
+
+    /// Maps a line and column in [`StrippedTokens::text`] back to the original input.
+    pub fn original_position(&self, line: usize, column: usize) -> Option<(usize, usize)> {
+        let index = self
+            .text_positions
+            .partition_point(|position| *position <= (line, column))
+            .checked_sub(1)?;
+        let (text_line, text_column) = self.text_positions[index];
+        let token = &self.tokens[index];
+
+        match line - text_line {
+            0 => Some((token.get_line(), token.get_column() + column - text_column)),
+            lines => Some((token.get_line() + lines, column)),
+        }
+    }
+}
+
+impl Collector {
+    /// Removes annotation tokens matched by a sink, along with the tokens of their parts
+    /// when `include_parts` is true. Nested blocks are always inside a part, so stripping
+    /// parts also strips every nested annotation. When a top level block's last part is
+    /// [`PartBehavior::UntilNewline`], the whitespace from its final newline onward is kept.
+    pub fn strip_annotations(
+        &self,
+        tokens: &[LexerToken],
+        include_parts: bool,
+    ) -> Result<StrippedTokens, String> {
+        let blocks = self.collect_tokens(tokens)?;
+        let removed = self.removed_positions(&blocks, include_parts);
+
+        let (origins, tokens): (Vec<usize>, Vec<LexerToken>) = tokens
+            .iter()
+            .enumerate()
+            .filter_map(|(index, token)| {
+                match removed.get(&(token.get_line(), token.get_column())) {
+                    None => Some((index, token.clone())),
+                    Some(kept) => kept.clone().map(|kept| (index, kept)),
+                }
+            })
+            .unzip();
+
+        Ok(StrippedTokens::new(tokens, origins))
+    }
+
+    pub fn strip_annotations_from_input(
+        &self,
+        input: &str,
+        include_parts: bool,
+    ) -> Result<StrippedTokens, String> {
+        let tokens = lex(input)?;
+        self.strip_annotations(&tokens, include_parts)
+    }
+
+    // Positions of tokens to remove, with what is kept of them if anything
+    fn removed_positions(
+        &self,
+        blocks: &[TokenBlock],
+        include_parts: bool,
+    ) -> HashMap<(usize, usize), Option<LexerToken>> {
+        let mut removed = HashMap::new();
+        for (depth, block) in iter_depth_first(blocks) {
+            if let Some(token) = block.annotation_token() {
+                removed.insert((token.get_line(), token.get_column()), None);
+            }
+            if !include_parts {
+                continue;
+            }
+            for token in block.parts().iter().flatten() {
+                removed.insert((token.get_line(), token.get_column()), None);
+            }
+
+            // A top level block ending on a newline keeps it, so the next line isn't joined on
+            let last = block.parts().len().checked_sub(1);
+            let behavior = last.and_then(|part| self.part_behavior(block.annotation_text(), part));
+            let token = block.parts().last().and_then(|part| part.last());
+            if let (0, Some(PartBehavior::UntilNewline), Some(token)) = (depth, behavior, token) {
+                if token.get_token_type() == TokenType::Whitespace {
+                    let kept = from_last_newline(token);
+                    removed.insert((token.get_line(), token.get_column()), kept);
+                }
+            }
+        }
+        removed
+    }
+}
+
+// Text of a token from its last newline onward, positioned where that newline is
+fn from_last_newline(token: &LexerToken) -> Option<LexerToken> {
+    let (start, _) = token.get_text().rmatch_indices('\n').next()?;
+    let before = &token.get_text()[..start];
+    let (line, column) = match before.rfind('\n') {
+        None => (
+            token.get_line(),
+            token.get_column() + before.chars().count(),
+        ),
+        Some(newline) => (
+            token.get_line() + before.matches('\n').count(),
+            before[newline + 1..].chars().count(),
+        ),
+    };
+
+    Some(LexerToken::new(
+        token.get_text()[start..].to_string(),
+        token.get_token_type(),
+        line,
+        column,
+    ))
+}
+
+#[cfg(test)]
+mod stripping {
+    use garnish_lang_compiler::lex::{LexerToken, TokenType};
+
+    use crate::{Collector, PartBehavior, PartParser, Sink};
+
+    fn collector() -> Collector {
+        Collector::new(vec![
+            Sink::new("@Test")
+                .part(PartParser::new(PartBehavior::TokenCount(1)))
+                .part(PartParser::new(PartBehavior::UntilToken(
+                    TokenType::EndExpression,
+                ))),
+            Sink::new("@Inline"),
+            Sink::new("@Def").part(PartParser::new(PartBehavior::UntilNewline)),
+            Sink::new("@Doc").part(PartParser::new(PartBehavior::TokenCount(1))),
+        ])
+    }
+
+    #[test]
+    fn annotations_and_parts() {
+        let input = "value = 5\n@Test name { value }\n@Other\nvalue + 1";
+
+        let stripped = collector()
+            .strip_annotations_from_input(input, true)
+            .unwrap();
+
+        assert_eq!(stripped.text(), "value = 5\n\n@Other\nvalue + 1");
+        assert_eq!(
+            stripped.origins(),
+            &vec![0, 1, 2, 3, 4, 5, 15, 16, 17, 18, 19, 20, 21, 22]
+        );
+    }
+
+    #[test]
+    fn newline_ending_part_kept() {
+        let input = "@Def add  \n  add = 5\n@Test name { @Def inner\n 5 }\nadd";
+
+        let stripped = collector()
+            .strip_annotations_from_input(input, true)
+            .unwrap();
+
+        assert_eq!(stripped.text(), "\n  add = 5\n\nadd");
+        assert_eq!(
+            stripped.tokens()[0],
+            LexerToken::new("\n  ".to_string(), TokenType::Whitespace, 0, 10)
+        );
+        assert_eq!(stripped.original_position(1, 2), Some((1, 2)));
+    }
+
+    #[test]
+    fn multiline_string_part_removed() {
+        let input = "@Doc \"a\nb\" x\n@Def \"c\nd\"\ny";
+
+        let stripped = collector()
+            .strip_annotations_from_input(input, true)
+            .unwrap();
+
+        assert_eq!(stripped.text(), " x\n\ny");
+    }
+
+    #[test]
+    fn annotations_only() {
+        let input = "@Inline add = 5\n@Test name { 5 }";
+
+        let stripped = collector()
+            .strip_annotations_from_input(input, false)
+            .unwrap();
+
+        assert_eq!(stripped.text(), " add = 5\n name { 5 }");
+        assert_eq!(
+            stripped.tokens()[0],
+            LexerToken::new(" ".to_string(), TokenType::Whitespace, 0, 7)
+        );
+    }
+
+    #[test]
+    fn maps_text_positions_to_original() {
+        let input = "@Test name { 5 }\n\nvalue +\n  bad";
+
+        let stripped = collector()
+            .strip_annotations_from_input(input, true)
+            .unwrap();
+
+        assert_eq!(stripped.text(), "\n\nvalue +\n  bad");
+        assert_eq!(stripped.original_position(0, 0), Some((0, 16)));
+        assert_eq!(stripped.original_position(1, 0), Some((1, 0)));
+        assert_eq!(stripped.original_position(2, 6), Some((2, 6)));
+        assert_eq!(stripped.original_position(3, 2), Some((3, 2)));
+    }
+
+    #[test]
+    fn modifiers() {
+        let input = "@Skip @Inline @Test name { 5 }\n@Test other { 6 }";
+        let modifiers = Collector::new(vec![
+            collector().sinks()[0].clone(),
+            Sink::new("@Inline").modifier(),
+            Sink::new("@Skip").modifier(),
+        ]);
+
+        let stripped = modifiers.strip_annotations_from_input(input, true).unwrap();
+
+        assert_eq!(
+            stripped,
+            Collector::new(vec![
+                collector().sinks()[0].clone(),
+                Sink::new("@Inline"),
+                Sink::new("@Skip")
+            ])
+            .strip_annotations_from_input(input, true)
+            .unwrap()
+        );
+        assert_eq!(stripped.text(), "  \n");
+    }
+}
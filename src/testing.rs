@@ -0,0 +1,501 @@
+// Test discovery using the standard garnish test annotations.
+//
+//     @Setup { ... }
+//     @Test addition {
+//         @Case small { 1 + 1 }
+//         @Skip @Case large { 1000 + 1000 }
+//     }
+//     @Only @Test subtraction { 5 - 1 }
+//     @Teardown { ... }
+//     @End
+//
+// `@End` closes a suite, blocks after it belong to the next one. `@Skip` and `@Only` are
+// modifiers, grouped onto the `@Test` or `@Case` that follows them.
+
+mod report;
+
+use std::collections::HashSet;
+
+use garnish_lang_compiler::lex::{LexerToken, TokenType};
+
+use crate::collector::{Collector, PartBehavior, PartParser, Sink, TokenBlock};
+use crate::diagnostic::{Diagnostic, DiagnosticSeverity};
+
+pub use report::*;
+
+pub const TEST: &str = "@Test";
+pub const CASE: &str = "@Case";
+pub const SKIP: &str = "@Skip";
+pub const ONLY: &str = "@Only";
+pub const SETUP: &str = "@Setup";
+pub const TEARDOWN: &str = "@Teardown";
+pub const END: &str = "@End";
+
+#[derive(Debug, Eq, PartialEq, Clone, Default)]
+pub struct TestPlan {
+    suites: Vec<TestSuite>,
+}
+
+impl TestPlan {
+    pub fn suites(&self) -> &Vec<TestSuite> {
+        &self.suites
+    }
+
+    /// Whether any test or case is marked `@Only`.
+    pub fn has_only(&self) -> bool {
+        self.suites
+            .iter()
+            .flat_map(|suite| suite.tests())
+            .any(|test| test.only() || test.cases().iter().any(|case| case.only()))
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Clone, Default)]
+pub struct TestSuite {
+    setup: Option<Vec<LexerToken>>,
+    teardown: Option<Vec<LexerToken>>,
+    tests: Vec<Test>,
+}
+
+impl TestSuite {
+    pub fn setup(&self) -> Option<&Vec<LexerToken>> {
+        self.setup.as_ref()
+    }
+
+    pub fn teardown(&self) -> Option<&Vec<LexerToken>> {
+        self.teardown.as_ref()
+    }
+
+    pub fn tests(&self) -> &Vec<Test> {
+        &self.tests
+    }
+
+    fn is_empty(&self) -> bool {
+        self.setup.is_none() && self.teardown.is_none() && self.tests.is_empty()
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct Test {
+    name: String,
+    line: usize,
+    column: usize,
+    body: Vec<LexerToken>,
+    cases: Vec<TestCase>,
+    skip: bool,
+    only: bool,
+}
+
+impl Test {
+    pub fn name(&self) -> &String {
+        &self.name
+    }
+
+    /// Line of the `@Test` annotation.
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    pub fn column(&self) -> usize {
+        self.column
+    }
+
+    /// Tokens between the braces of the test, without any `@Case` blocks.
+    pub fn body(&self) -> &Vec<LexerToken> {
+        &self.body
+    }
+
+    pub fn cases(&self) -> &Vec<TestCase> {
+        &self.cases
+    }
+
+    pub fn skip(&self) -> bool {
+        self.skip
+    }
+
+    pub fn only(&self) -> bool {
+        self.only
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct TestCase {
+    name: String,
+    line: usize,
+    column: usize,
+    body: Vec<LexerToken>,
+    skip: bool,
+    only: bool,
+}
+
+impl TestCase {
+    pub fn name(&self) -> &String {
+        &self.name
+    }
+
+    /// Line of the `@Case` annotation.
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    pub fn column(&self) -> usize {
+        self.column
+    }
+
+    /// Tokens between the braces of the case.
+    pub fn body(&self) -> &Vec<LexerToken> {
+        &self.body
+    }
+
+    pub fn skip(&self) -> bool {
+        self.skip
+    }
+
+    pub fn only(&self) -> bool {
+        self.only
+    }
+}
+
+/// Collector with sinks for all standard test annotations.
+pub fn collector() -> Collector {
+    let body = || {
+        PartParser::new(PartBehavior::StartEnd {
+            start: TokenType::StartExpression,
+            end: TokenType::EndExpression,
+        })
+    };
+
+    Collector::new(vec![
+        Sink::new(TEST)
+            .top_level_only()
+            .part(PartParser::new(PartBehavior::TokenCount(1)))
+            .part(body()),
+        Sink::new(CASE)
+            .allowed_parents([TEST])
+            .part(PartParser::new(PartBehavior::TokenCount(1)))
+            .part(body()),
+        Sink::new(SKIP).modifier(),
+        Sink::new(ONLY).modifier(),
+        Sink::new(SETUP).part(body()),
+        Sink::new(TEARDOWN).part(body()),
+        Sink::new(END),
+    ])
+}
+
+/// Collects `input` with [`collector`] and builds its [`TestPlan`], reporting every error found
+/// collecting along with those of [`test_plan`].
+pub fn discover(input: &str) -> Result<TestPlan, Vec<Diagnostic>> {
+    let (blocks, diagnostics) = collector().recover_input(input);
+    let mut errors: Vec<Diagnostic> = diagnostics
+        .into_iter()
+        .filter(|diagnostic| diagnostic.severity() == DiagnosticSeverity::Error)
+        .collect();
+    let plan_errors = match test_plan(&blocks) {
+        Ok(plan) if errors.is_empty() => return Ok(plan),
+        Ok(_) => vec![],
+        Err(plan_errors) => plan_errors,
+    };
+
+    // Misplaced annotations are reported by both, keep the plan's message for them
+    errors.retain(|error| {
+        !plan_errors.iter().any(|plan_error| {
+            (plan_error.line(), plan_error.column()) == (error.line(), error.column())
+        })
+    });
+    errors.extend(plan_errors);
+    errors.sort_by_key(|error| (error.line(), error.column()));
+    Err(errors)
+}
+
+/// Builds a [`TestPlan`] from blocks collected with [`collector`], reporting every misplaced
+/// annotation, missing name or body and duplicate name.
+pub fn test_plan(blocks: &[TokenBlock]) -> Result<TestPlan, Vec<Diagnostic>> {
+    let mut plan = TestPlan::default();
+    let mut suite = TestSuite::default();
+    let mut errors = vec![];
+    let mut names = HashSet::new();
+
+    for block in blocks {
+        match block.annotation_text().as_str() {
+            TEST => {
+                let test = build_test(block, &mut errors);
+                if let Some(test) = test {
+                    if !names.insert(test.name.clone()) {
+                        errors.push(error(block, format!("Duplicate test name '{}'", test.name)));
+                    }
+                    suite.tests.push(test);
+                }
+            }
+            SETUP | TEARDOWN => {
+                dangling(block.modifiers(), &mut errors);
+                let existing = match block.annotation_text().as_str() {
+                    SETUP => &mut suite.setup,
+                    _ => &mut suite.teardown,
+                };
+                match existing {
+                    Some(_) => errors.push(error(
+                        block,
+                        format!("Suite already has a {}", block.annotation_text()),
+                    )),
+                    None => *existing = body(block, 0, &mut errors),
+                }
+            }
+            END => {
+                dangling(block.modifiers(), &mut errors);
+                plan.suites.push(std::mem::take(&mut suite));
+                names.clear();
+            }
+            CASE => {
+                dangling(block.modifiers(), &mut errors);
+                errors.push(error(block, format!("{} must be inside a {}", CASE, TEST)));
+            }
+            SKIP | ONLY => dangling(std::slice::from_ref(block), &mut errors),
+            _ => (),
+        }
+    }
+
+    if !suite.is_empty() {
+        plan.suites.push(suite);
+    }
+
+    match errors.is_empty() {
+        true => Ok(plan),
+        false => Err(errors),
+    }
+}
+
+fn build_test(block: &TokenBlock, errors: &mut Vec<Diagnostic>) -> Option<Test> {
+    let name = name(block, errors);
+    let body = body(block, 1, errors);
+
+    let mut cases = vec![];
+    let mut names = HashSet::new();
+    for child in block.blocks() {
+        match child.annotation_text().as_str() {
+            CASE => {
+                let case = build_case(child, errors);
+                if let Some(case) = case {
+                    if !names.insert(case.name.clone()) {
+                        errors.push(error(child, format!("Duplicate case name '{}'", case.name)));
+                    }
+                    cases.push(case);
+                }
+            }
+            TEST | SETUP | TEARDOWN | END => {
+                dangling(child.modifiers(), errors);
+                errors.push(error(
+                    child,
+                    format!("{} cannot be inside a {}", child.annotation_text(), TEST),
+                ));
+            }
+            SKIP | ONLY => dangling(std::slice::from_ref(child), errors),
+            _ => (),
+        }
+    }
+
+    let (skip, only) = flags(block);
+    let (line, column) = position(block);
+    Some(Test {
+        name: name?,
+        line,
+        column,
+        body: body?,
+        cases,
+        skip,
+        only,
+    })
+}
+
+fn build_case(block: &TokenBlock, errors: &mut Vec<Diagnostic>) -> Option<TestCase> {
+    let name = name(block, errors);
+    let body = body(block, 1, errors);
+    for child in block.blocks() {
+        errors.push(error(
+            child,
+            format!("{} cannot be inside a {}", child.annotation_text(), CASE),
+        ));
+    }
+
+    let (skip, only) = flags(block);
+    let (line, column) = position(block);
+    Some(TestCase {
+        name: name?,
+        line,
+        column,
+        body: body?,
+        skip,
+        only,
+    })
+}
+
+// Skip and only flags from the modifiers grouped onto a test or case
+fn flags(block: &TokenBlock) -> (bool, bool) {
+    let has = |text: &str| {
+        block
+            .modifiers()
+            .iter()
+            .any(|modifier| modifier.annotation_text() == text)
+    };
+    (has(SKIP), has(ONLY))
+}
+
+fn dangling(modifiers: &[TokenBlock], errors: &mut Vec<Diagnostic>) {
+    for block in modifiers {
+        errors.push(error(
+            block,
+            format!(
+                "{} must be followed by a {} or {}",
+                block.annotation_text(),
+                TEST,
+                CASE
+            ),
+        ));
+    }
+}
+
+fn name(block: &TokenBlock, errors: &mut Vec<Diagnostic>) -> Option<String> {
+    let token = block
+        .parts()
+        .first()
+        .and_then(|part| {
+            part.iter()
+                .find(|token| token.get_token_type() != TokenType::Whitespace)
+        })
+        .filter(|token| {
+            matches!(
+                token.get_token_type(),
+                TokenType::Identifier | TokenType::Symbol | TokenType::CharList
+            )
+        });
+
+    match token {
+        Some(token) => Some(token.get_text().trim_matches('"').to_string()),
+        None => {
+            errors.push(error(
+                block,
+                format!("Expected a name after {}", block.annotation_text()),
+            ));
+            None
+        }
+    }
+}
+
+// Tokens between the outermost braces of a part
+fn body(block: &TokenBlock, part: usize, errors: &mut Vec<Diagnostic>) -> Option<Vec<LexerToken>> {
+    let tokens = block.parts().get(part).map(Vec::as_slice).unwrap_or(&[]);
+    let start = tokens
+        .iter()
+        .position(|token| token.get_token_type() == TokenType::StartExpression);
+    let end = tokens
+        .iter()
+        .rposition(|token| token.get_token_type() == TokenType::EndExpression);
+
+    match (start, end) {
+        (Some(start), Some(end)) if start < end => Some(tokens[start + 1..end].to_vec()),
+        _ => {
+            errors.push(error(
+                block,
+                format!("Expected a body in braces for {}", block.annotation_text()),
+            ));
+            None
+        }
+    }
+}
+
+fn position(block: &TokenBlock) -> (usize, usize) {
+    block
+        .annotation_token()
+        .map(|token| (token.get_line(), token.get_column()))
+        .unwrap_or((0, 0))
+}
+
+fn error(block: &TokenBlock, message: String) -> Diagnostic {
+    let (line, column) = position(block);
+    Diagnostic::new(message, line, column)
+}
+
+#[cfg(test)]
+mod discovering {
+    use crate::testing::discover;
+    use crate::Diagnostic;
+
+    fn texts(tokens: &[garnish_lang_compiler::lex::LexerToken]) -> String {
+        tokens
+            .iter()
+            .map(|token| token.get_text().as_str())
+            .collect()
+    }
+
+    #[test]
+    fn suites_tests_and_cases() {
+        let plan = discover(
+            "@Setup { 1 }\n@Test addition {\n  5 + 5\n  @Case small { 1 }\n  @Skip @Case large { 1000 }\n}\n@Only @Test subtraction { 5 - 1 }\n@End\n@Test other { 2 }\n",
+        )
+        .unwrap();
+
+        assert_eq!(plan.suites().len(), 2);
+
+        let suite = &plan.suites()[0];
+        assert_eq!(texts(suite.setup().unwrap()), " 1 ");
+        assert_eq!(suite.teardown(), None);
+        assert_eq!(suite.tests().len(), 2);
+
+        let addition = &suite.tests()[0];
+        assert_eq!(addition.name(), "addition");
+        assert_eq!((addition.line(), addition.column()), (1, 0));
+        assert_eq!(texts(addition.body()), "\n  5 + 5\n  \n   \n");
+        assert!(!addition.skip());
+        assert_eq!(addition.cases().len(), 2);
+        assert_eq!(addition.cases()[0].name(), "small");
+        assert_eq!(texts(addition.cases()[0].body()), " 1 ");
+        assert!(!addition.cases()[0].skip());
+        assert!(addition.cases()[1].skip());
+        assert_eq!(addition.cases()[1].line(), 4);
+
+        assert!(suite.tests()[1].only());
+        assert!(plan.has_only());
+
+        assert_eq!(plan.suites()[1].tests()[0].name(), "other");
+    }
+
+    #[test]
+    fn empty_input() {
+        assert!(discover("5 + 5").unwrap().suites().is_empty());
+    }
+
+    #[test]
+    fn collection_errors() {
+        assert_eq!(
+            discover("@Test a 5\n@Test b { 1 }").map(|plan| plan.suites().len()),
+            Err(vec![
+                Diagnostic::new("Expected a body in braces for @Test", 0, 0),
+                Diagnostic::new(
+                    "Part 2 of @Test should start with StartExpression, found '5'",
+                    0,
+                    8
+                ),
+            ])
+        );
+    }
+
+    #[test]
+    fn validation_errors() {
+        let errors = discover(
+            "@Case stray { 1 }\n@Test a { 1 }\n@Test a { 2 }\n@Skip 5\n@Test 5 { 3 }\n@Setup { }\n@Setup { }\n@Test b { @Test c { 1 } }\n@Only",
+        )
+        .unwrap_err();
+
+        assert_eq!(
+            errors,
+            vec![
+                Diagnostic::new("@Case must be inside a @Test", 0, 0),
+                Diagnostic::new("Duplicate test name 'a'", 2, 0),
+                Diagnostic::new("@Skip must be followed by a @Test or @Case", 3, 0),
+                Diagnostic::new("Expected a name after @Test", 4, 0),
+                Diagnostic::new("Suite already has a @Setup", 6, 0),
+                Diagnostic::new("@Test cannot be inside a @Test", 7, 10),
+                Diagnostic::new("@Only must be followed by a @Test or @Case", 8, 0),
+            ]
+        );
+    }
+}
@@ -0,0 +1,370 @@
+use std::collections::HashMap;
+
+use crate::testing::TestPlan;
+
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum Outcome {
+    Passed,
+    Failed(String),
+    Skipped,
+}
+
+/// Outcomes of running a [`TestPlan`], keyed by suite index, test name and case name.
+#[derive(Debug, Eq, PartialEq, Clone, Default)]
+pub struct TestResults {
+    outcomes: HashMap<(usize, String, Option<String>), Outcome>,
+}
+
+impl TestResults {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, suite: usize, test: &str, case: Option<&str>, outcome: Outcome) {
+        self.outcomes
+            .insert((suite, test.to_string(), case.map(str::to_string)), outcome);
+    }
+
+    pub fn get(&self, suite: usize, test: &str, case: Option<&str>) -> Option<&Outcome> {
+        self.outcomes
+            .get(&(suite, test.to_string(), case.map(str::to_string)))
+    }
+}
+
+// A test without cases, or a single case of a test
+struct Entry {
+    suite: usize,
+    test: String,
+    case: Option<String>,
+    line: usize,
+    outcome: Outcome,
+}
+
+impl Entry {
+    fn name(&self) -> String {
+        match &self.case {
+            None => self.test.clone(),
+            Some(case) => format!("{}/{}", self.test, case),
+        }
+    }
+}
+
+// Tests marked to skip, or left out by an `@Only` elsewhere, are skipped regardless of results.
+// Entries without a result were not run and are also skipped.
+fn entries(plan: &TestPlan, results: Option<&TestResults>) -> Vec<Entry> {
+    let has_only = plan.has_only();
+    let outcome = |suite: usize, test: &str, case: Option<&str>, skipped: bool| match skipped {
+        true => Outcome::Skipped,
+        false => results
+            .and_then(|results| results.get(suite, test, case))
+            .cloned()
+            .unwrap_or(Outcome::Skipped),
+    };
+
+    let mut entries = vec![];
+    for (suite, tests) in plan.suites().iter().enumerate() {
+        for test in tests.tests() {
+            if test.cases().is_empty() {
+                let skipped = test.skip() || (has_only && !test.only());
+                entries.push(Entry {
+                    suite,
+                    test: test.name().clone(),
+                    case: None,
+                    line: test.line(),
+                    outcome: outcome(suite, test.name(), None, skipped),
+                });
+            }
+
+            for case in test.cases() {
+                let skipped =
+                    test.skip() || case.skip() || (has_only && !test.only() && !case.only());
+                entries.push(Entry {
+                    suite,
+                    test: test.name().clone(),
+                    case: Some(case.name().clone()),
+                    line: case.line(),
+                    outcome: outcome(suite, test.name(), Some(case.name()), skipped),
+                });
+            }
+        }
+    }
+    entries
+}
+
+/// Writes a plan as JUnit XML with one `testsuite` per suite of `file`, and one `testcase`
+/// per case, or per test for tests without cases. Tests without a result, or every test when
+/// there are no results, are listed as skipped.
+pub fn junit_xml(plan: &TestPlan, file: &str, results: Option<&TestResults>) -> String {
+    let entries = entries(plan, results);
+    let count = |entries: &[&Entry], outcome: fn(&Outcome) -> bool| {
+        entries
+            .iter()
+            .filter(|entry| outcome(&entry.outcome))
+            .count()
+    };
+    let failed = |outcome: &Outcome| matches!(outcome, Outcome::Failed(_));
+    let skipped = |outcome: &Outcome| outcome == &Outcome::Skipped;
+
+    let all: Vec<&Entry> = entries.iter().collect();
+    let mut output = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    output.push_str(&format!(
+        "<testsuites tests=\"{}\" failures=\"{}\" skipped=\"{}\">\n",
+        all.len(),
+        count(&all, failed),
+        count(&all, skipped)
+    ));
+
+    for suite in 0..plan.suites().len() {
+        let suite_entries: Vec<&Entry> = entries.iter().filter(|e| e.suite == suite).collect();
+        let name = match plan.suites().len() {
+            1 => file.to_string(),
+            _ => format!("{}#{}", file, suite + 1),
+        };
+        output.push_str(&format!(
+            "  <testsuite name=\"{}\" tests=\"{}\" failures=\"{}\" skipped=\"{}\">\n",
+            escape(&name),
+            suite_entries.len(),
+            count(&suite_entries, failed),
+            count(&suite_entries, skipped)
+        ));
+
+        for entry in suite_entries {
+            let (name, classname) = match &entry.case {
+                None => (&entry.test, &name),
+                Some(case) => (case, &entry.test),
+            };
+            let attributes = format!(
+                "name=\"{}\" classname=\"{}\" file=\"{}\" line=\"{}\"",
+                escape(name),
+                escape(classname),
+                escape(file),
+                entry.line + 1
+            );
+
+            match &entry.outcome {
+                Outcome::Passed => output.push_str(&format!("    <testcase {}/>\n", attributes)),
+                Outcome::Skipped => output.push_str(&format!(
+                    "    <testcase {}>\n      <skipped/>\n    </testcase>\n",
+                    attributes
+                )),
+                Outcome::Failed(message) => output.push_str(&format!(
+                    "    <testcase {}>\n      <failure message=\"{}\"/>\n    </testcase>\n",
+                    attributes,
+                    escape(message)
+                )),
+            }
+        }
+
+        output.push_str("  </testsuite>\n");
+    }
+
+    output.push_str("</testsuites>\n");
+    output
+}
+
+/// Writes a plan as TAP version 13, naming each test point `test` or `test/case`.
+/// Tests without a result, or every test when there are no results, are reported as `# SKIP`.
+/// Failure messages are written as YAML block scalars.
+pub fn tap(plan: &TestPlan, file: &str, results: Option<&TestResults>) -> String {
+    let entries = entries(plan, results);
+
+    let mut output = format!("TAP version 13\n1..{}\n", entries.len());
+    for (index, entry) in entries.iter().enumerate() {
+        let number = index + 1;
+        let description = tap_description(&entry.name());
+        match &entry.outcome {
+            Outcome::Passed => output.push_str(&format!("ok {} - {}\n", number, description)),
+            Outcome::Skipped => {
+                output.push_str(&format!("ok {} - {} # SKIP\n", number, description))
+            }
+            Outcome::Failed(message) => {
+                output.push_str(&format!("not ok {} - {}\n", number, description));
+                output.push_str("  ---\n");
+                output.push_str(&format!("  message: {}\n", yaml_block(message)));
+                output.push_str(&format!("  at: {}:{}\n", file, entry.line + 1));
+                output.push_str("  ...\n");
+            }
+        }
+    }
+    output
+}
+
+// A `#` would start a directive, so it's escaped along with backslashes, as TAP 13 allows
+fn tap_description(name: &str) -> String {
+    name.replace('\\', "\\\\").replace('#', "\\#")
+}
+
+// Literal block scalar for a value of the two space indented TAP diagnostic, keeping `text` as is
+fn yaml_block(text: &str) -> String {
+    // Leading spaces would be taken as indentation without an explicit indicator
+    let indicator = match text.lines().find(|line| !line.is_empty()) {
+        Some(line) if line.starts_with(' ') => "2",
+        _ => "",
+    };
+    let chomping = match text.ends_with('\n') {
+        true => "+",
+        false => "-",
+    };
+
+    let mut block = format!("|{}{}", indicator, chomping);
+    for line in text.strip_suffix('\n').unwrap_or(text).split('\n') {
+        block.push('\n');
+        if !line.is_empty() {
+            block.push_str("    ");
+            block.push_str(line);
+        }
+    }
+    block
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+#[cfg(test)]
+mod reporting {
+    use crate::testing::{discover, junit_xml, tap, Outcome, TestResults};
+
+    const INPUT: &str = "@Test addition {\n  @Case small { 1 }\n  @Skip @Case large { 2 }\n}\n@Test \"a<b\" { 5 }\n";
+
+    fn results() -> TestResults {
+        let mut results = TestResults::new();
+        results.insert(0, "addition", Some("small"), Outcome::Passed);
+        results.insert(
+            0,
+            "a<b",
+            None,
+            Outcome::Failed("expected \"true\"".to_string()),
+        );
+        results
+    }
+
+    #[test]
+    fn junit_plan_only() {
+        let plan = discover(INPUT).unwrap();
+
+        assert_eq!(
+            junit_xml(&plan, "math.garnish", None),
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<testsuites tests=\"3\" failures=\"0\" skipped=\"3\">
+  <testsuite name=\"math.garnish\" tests=\"3\" failures=\"0\" skipped=\"3\">
+    <testcase name=\"small\" classname=\"addition\" file=\"math.garnish\" line=\"2\">
+      <skipped/>
+    </testcase>
+    <testcase name=\"large\" classname=\"addition\" file=\"math.garnish\" line=\"3\">
+      <skipped/>
+    </testcase>
+    <testcase name=\"a&lt;b\" classname=\"math.garnish\" file=\"math.garnish\" line=\"5\">
+      <skipped/>
+    </testcase>
+  </testsuite>
+</testsuites>
+"
+        );
+    }
+
+    #[test]
+    fn junit_with_results() {
+        let plan = discover(INPUT).unwrap();
+
+        let output = junit_xml(&plan, "math.garnish", Some(&results()));
+
+        assert!(output.contains("<testsuites tests=\"3\" failures=\"1\" skipped=\"1\">"));
+        assert!(output.contains("      <failure message=\"expected &quot;true&quot;\"/>\n"));
+    }
+
+    #[test]
+    fn tap_with_results() {
+        let plan = discover(INPUT).unwrap();
+
+        assert_eq!(
+            tap(&plan, "math.garnish", Some(&results())),
+            "TAP version 13
+1..3
+ok 1 - addition/small
+ok 2 - addition/large # SKIP
+not ok 3 - a<b
+  ---
+  message: |-
+    expected \"true\"
+  at: math.garnish:5
+  ...
+"
+        );
+    }
+
+    #[test]
+    fn missing_results_are_skipped() {
+        let plan = discover("@Test a { 1 }\n@End\n@Test a { 2 }").unwrap();
+        let mut results = TestResults::new();
+        results.insert(1, "a", None, Outcome::Passed);
+
+        assert_eq!(
+            tap(&plan, "a.garnish", Some(&results)),
+            "TAP version 13\n1..2\nok 1 - a # SKIP\nok 2 - a\n"
+        );
+    }
+
+    #[test]
+    fn only_skips_others() {
+        let plan = discover("@Test a { 1 }\n@Only @Test b { 2 }").unwrap();
+        let mut results = TestResults::new();
+        results.insert(0, "a", None, Outcome::Passed);
+        results.insert(0, "b", None, Outcome::Passed);
+
+        assert_eq!(
+            tap(&plan, "a.garnish", Some(&results)),
+            "TAP version 13\n1..2\nok 1 - a # SKIP\nok 2 - b\n"
+        );
+    }
+
+    #[test]
+    fn tap_plan_only() {
+        let plan = discover("@Test a { 1 }").unwrap();
+
+        assert_eq!(
+            tap(&plan, "a.garnish", None),
+            "TAP version 13\n1..1\nok 1 - a # SKIP\n"
+        );
+    }
+
+    #[test]
+    fn tap_escapes_directives() {
+        let plan = discover("@Test \"a # b\\c\" { 1 }").unwrap();
+
+        assert_eq!(
+            tap(&plan, "a.garnish", None),
+            "TAP version 13\n1..1\nok 1 - a \\# b\\\\c # SKIP\n"
+        );
+    }
+
+    #[test]
+    fn tap_multiline_message() {
+        let plan = discover("@Test a { 1 }").unwrap();
+        let mut results = TestResults::new();
+        results.insert(
+            0,
+            "a",
+            None,
+            Outcome::Failed("  left: 1\nright: 2\n".to_string()),
+        );
+
+        assert_eq!(
+            tap(&plan, "a.garnish", Some(&results)),
+            "TAP version 13
+1..1
+not ok 1 - a
+  ---
+  message: |2+
+      left: 1
+    right: 2
+  at: a.garnish:1
+  ...
+"
+        );
+    }
+}
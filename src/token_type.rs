@@ -0,0 +1,87 @@
+use garnish_lang_compiler::lex::TokenType;
+
+const TOKEN_TYPES: &[TokenType] = &[
+    TokenType::Unknown,
+    TokenType::UnitLiteral,
+    TokenType::Symbol,
+    TokenType::Number,
+    TokenType::CharList,
+    TokenType::ByteList,
+    TokenType::Identifier,
+    TokenType::Property,
+    TokenType::Whitespace,
+    TokenType::Subexpression,
+    TokenType::Annotation,
+    TokenType::LineAnnotation,
+    TokenType::Value,
+    TokenType::True,
+    TokenType::False,
+    TokenType::StartExpression,
+    TokenType::EndExpression,
+    TokenType::StartGroup,
+    TokenType::EndGroup,
+    TokenType::StartSideEffect,
+    TokenType::EndSideEffect,
+    TokenType::PlusSign,
+    TokenType::Subtraction,
+    TokenType::MultiplicationSign,
+    TokenType::ExponentialSign,
+    TokenType::Division,
+    TokenType::IntegerDivision,
+    TokenType::Remainder,
+    TokenType::Equality,
+    TokenType::Inequality,
+    TokenType::LessThan,
+    TokenType::LessThanOrEqual,
+    TokenType::GreaterThan,
+    TokenType::GreaterThanOrEqual,
+    TokenType::And,
+    TokenType::Or,
+    TokenType::Xor,
+    TokenType::Not,
+    TokenType::Period,
+    TokenType::Pair,
+    TokenType::Range,
+    TokenType::Comma,
+    TokenType::Apply,
+    TokenType::ApplyTo,
+    TokenType::Reapply,
+    TokenType::Concatenation,
+    TokenType::EmptyApply,
+    TokenType::JumpIfTrue,
+    TokenType::JumpIfFalse,
+    TokenType::ElseJump,
+];
+
+/// Name of a token type as written in configuration and serialized output, e.g. `EndExpression`.
+pub fn token_type_name(token_type: TokenType) -> String {
+    format!("{:?}", token_type)
+}
+
+pub fn token_type_from_name(name: &str) -> Option<TokenType> {
+    TOKEN_TYPES
+        .iter()
+        .find(|token_type| token_type_name(**token_type) == name)
+        .copied()
+}
+
+#[cfg(test)]
+mod names {
+    use garnish_lang_compiler::lex::TokenType;
+
+    use crate::{token_type_from_name, token_type_name};
+
+    #[test]
+    fn round_trip() {
+        assert_eq!(token_type_name(TokenType::EndExpression), "EndExpression");
+        assert_eq!(
+            token_type_from_name("EndExpression"),
+            Some(TokenType::EndExpression)
+        );
+    }
+
+    #[test]
+    fn unknown_name() {
+        assert_eq!(token_type_from_name("NotAToken"), None);
+    }
+}
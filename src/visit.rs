@@ -0,0 +1,238 @@
+use garnish_lang_compiler::lex::LexerToken;
+
+use crate::collector::TokenBlock;
+
+/// Hooks called while walking a block tree. Blocks are entered, their parts visited in order,
+/// then their nested blocks walked before the block is left. A block's modifiers are walked
+/// just before it, at the same depth.
+pub trait Visitor {
+    fn enter_block(&mut self, _depth: usize, _block: &TokenBlock) {}
+
+    fn visit_part(&mut self, _depth: usize, _index: usize, _part: &[LexerToken]) {}
+
+    fn leave_block(&mut self, _depth: usize, _block: &TokenBlock) {}
+}
+
+/// Same as [`Visitor`] but with mutable access to blocks and parts.
+pub trait VisitorMut {
+    fn enter_block(&mut self, _depth: usize, _block: &mut TokenBlock) {}
+
+    fn visit_part(&mut self, _depth: usize, _index: usize, _part: &mut Vec<LexerToken>) {}
+
+    fn leave_block(&mut self, _depth: usize, _block: &mut TokenBlock) {}
+}
+
+pub struct DepthFirst<'a> {
+    stack: Vec<(usize, &'a TokenBlock)>,
+}
+
+impl<'a> DepthFirst<'a> {
+    fn new(blocks: &'a [TokenBlock], depth: usize) -> Self {
+        let mut iter = Self { stack: vec![] };
+        iter.push_all(blocks, depth);
+        iter
+    }
+
+    // Modifiers go on top of their block so they're yielded first
+    fn push_all(&mut self, blocks: &'a [TokenBlock], depth: usize) {
+        for block in blocks.iter().rev() {
+            self.stack.push((depth, block));
+            self.stack.extend(
+                block
+                    .modifiers()
+                    .iter()
+                    .rev()
+                    .map(|modifier| (depth, modifier)),
+            );
+        }
+    }
+}
+
+impl<'a> Iterator for DepthFirst<'a> {
+    type Item = (usize, &'a TokenBlock);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (depth, block) = self.stack.pop()?;
+        self.push_all(block.blocks(), depth + 1);
+        Some((depth, block))
+    }
+}
+
+impl TokenBlock {
+    pub fn iter_depth_first(&self) -> DepthFirst<'_> {
+        DepthFirst::new(std::slice::from_ref(self), 0)
+    }
+
+    pub fn walk<V: Visitor>(&self, visitor: &mut V) {
+        walk_block(self, 0, visitor);
+    }
+
+    pub fn walk_mut<V: VisitorMut>(&mut self, visitor: &mut V) {
+        walk_block_mut(self, 0, visitor);
+    }
+}
+
+pub fn iter_depth_first(blocks: &[TokenBlock]) -> DepthFirst<'_> {
+    DepthFirst::new(blocks, 0)
+}
+
+pub fn walk<V: Visitor>(blocks: &[TokenBlock], visitor: &mut V) {
+    for block in blocks {
+        walk_block(block, 0, visitor);
+    }
+}
+
+pub fn walk_mut<V: VisitorMut>(blocks: &mut [TokenBlock], visitor: &mut V) {
+    for block in blocks {
+        walk_block_mut(block, 0, visitor);
+    }
+}
+
+fn walk_block<V: Visitor>(block: &TokenBlock, depth: usize, visitor: &mut V) {
+    for modifier in block.modifiers() {
+        walk_block(modifier, depth, visitor);
+    }
+    visitor.enter_block(depth, block);
+    for (index, part) in block.parts().iter().enumerate() {
+        visitor.visit_part(depth, index, part);
+    }
+    for child in block.blocks() {
+        walk_block(child, depth + 1, visitor);
+    }
+    visitor.leave_block(depth, block);
+}
+
+fn walk_block_mut<V: VisitorMut>(block: &mut TokenBlock, depth: usize, visitor: &mut V) {
+    for modifier in block.modifiers_mut() {
+        walk_block_mut(modifier, depth, visitor);
+    }
+    visitor.enter_block(depth, block);
+    for (index, part) in block.parts_mut().iter_mut().enumerate() {
+        visitor.visit_part(depth, index, part);
+    }
+    for child in block.blocks_mut() {
+        walk_block_mut(child, depth + 1, visitor);
+    }
+    visitor.leave_block(depth, block);
+}
+
+#[cfg(test)]
+mod walking {
+    use garnish_lang_compiler::lex::{LexerToken, TokenType};
+
+    use crate::{
+        iter_depth_first, walk, walk_mut, Collector, PartBehavior, PartParser, Sink, TokenBlock,
+        Visitor, VisitorMut,
+    };
+
+    fn collect(input: &str) -> Vec<TokenBlock> {
+        Collector::new(vec![
+            Sink::new("@Test").part(PartParser::new(PartBehavior::UntilAnnotation(
+                "End".to_string(),
+            ))),
+            Sink::new("@Case").part(PartParser::new(PartBehavior::UntilToken(
+                TokenType::EndExpression,
+            ))),
+        ])
+        .collect_tokens_from_input(input)
+        .unwrap()
+    }
+
+    #[test]
+    fn depth_first_order() {
+        let blocks = collect("@Test 5\n@Case {10}\n@Case {20}\n@End 5 @Test 1 @End");
+
+        let visited: Vec<(usize, String)> = iter_depth_first(&blocks)
+            .map(|(depth, block)| (depth, block.annotation_text().clone()))
+            .collect();
+
+        assert_eq!(
+            visited,
+            vec![
+                (0, "@Test".to_string()),
+                (1, "@Case".to_string()),
+                (1, "@Case".to_string()),
+                (0, "".to_string()),
+                (0, "@Test".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn block_iterator_starts_at_self() {
+        let blocks = collect("@Test 5\n@Case {10}\n@End");
+
+        let depths: Vec<usize> = blocks[0]
+            .iter_depth_first()
+            .map(|(depth, _)| depth)
+            .collect();
+
+        assert_eq!(depths, vec![0, 1]);
+    }
+
+    #[derive(Default)]
+    struct Recorder {
+        events: Vec<String>,
+    }
+
+    impl Visitor for Recorder {
+        fn enter_block(&mut self, depth: usize, block: &TokenBlock) {
+            self.events
+                .push(format!("enter {} {}", depth, block.annotation_text()));
+        }
+
+        fn visit_part(&mut self, depth: usize, index: usize, part: &[LexerToken]) {
+            self.events
+                .push(format!("part {} {} {}", depth, index, part.len()));
+        }
+
+        fn leave_block(&mut self, depth: usize, block: &TokenBlock) {
+            self.events
+                .push(format!("leave {} {}", depth, block.annotation_text()));
+        }
+    }
+
+    #[test]
+    fn visitor_hooks() {
+        let blocks = collect("@Test 5\n@Case {10}\n@End");
+        let mut recorder = Recorder::default();
+
+        walk(&blocks, &mut recorder);
+
+        assert_eq!(
+            recorder.events,
+            vec![
+                "enter 0 @Test",
+                "part 0 0 5",
+                "enter 1 @Case",
+                "part 1 0 4",
+                "leave 1 @Case",
+                "leave 0 @Test",
+            ]
+        );
+    }
+
+    struct DropWhitespace;
+
+    impl VisitorMut for DropWhitespace {
+        fn visit_part(&mut self, _depth: usize, _index: usize, part: &mut Vec<LexerToken>) {
+            part.retain(|token| token.get_token_type() != TokenType::Whitespace);
+        }
+    }
+
+    #[test]
+    fn mutable_visitor() {
+        let mut blocks = collect("@Test 5\n@Case { 10 }\n@End");
+
+        walk_mut(&mut blocks, &mut DropWhitespace);
+
+        assert_eq!(
+            blocks[0].blocks()[0].parts(),
+            &vec![vec![
+                LexerToken::new("{".to_string(), TokenType::StartExpression, 1, 6),
+                LexerToken::new("10".to_string(), TokenType::Number, 1, 8),
+                LexerToken::new("}".to_string(), TokenType::EndExpression, 1, 11),
+            ]]
+        );
+    }
+}